@@ -0,0 +1,86 @@
+//! Times arithmetic-heavy scripts with each evaluator.
+//!
+//! Run with `cargo bench`, optionally naming the scripts to run:
+//! `cargo bench -- fib`.
+
+use rusp::{Evaluator, Interpreter};
+use std::{
+    env, hint,
+    time::{Duration, Instant},
+};
+
+const FIB: &str = "
+(fun {fib n} {if (< n 2) {n} {+ (fib (- n 1)) (fib (- n 2))}})
+(fib 20)";
+
+const LOOP: &str = "
+(fun {loop n acc} {if (== n 0) {acc} {loop (- n 1) (+ acc (* n n))}})
+(loop 50000 0)";
+
+const POLY: &str = "
+(fun {poly x} {+ (* 3 x x x) (* -2 x x) (* 5 x) -7})
+(fun {sum n acc} {if (== n 0) {acc} {sum (- n 1) (+ acc (poly n))}})
+(sum 20000 0)";
+
+const FOLD: &str = "
+(foldl + 0 (map (\\ {x} {% (* x x) 7}) (range 20000)))";
+
+const LET: &str = "
+(fun {dist n acc} {
+  let {dx (- n 3) dy (+ n 4)}
+    {if (== n 0) {acc} {dist (- n 1) (+ acc (* dx dx) (* dy dy))}}})
+(dist 20000 0)";
+
+const BENCHES: &[(&str, &str)] = &[
+    ("fib", FIB),
+    ("loop", LOOP),
+    ("poly", POLY),
+    ("fold", FOLD),
+    ("let", LET),
+];
+
+/* How long to run each script for, at the least */
+const TARGET: Duration = Duration::from_secs(1);
+
+/* The fastest of runs of script, taking about TARGET in all */
+fn time(evaluator: Evaluator, script: &str) -> (Duration, usize) {
+    let mut best = Duration::MAX;
+    let mut runs = 0;
+    let start = Instant::now();
+    while runs < 3 || start.elapsed() < TARGET {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_evaluator(evaluator);
+        let run = Instant::now();
+        let value = interpreter.eval_source("bench.lspy", script);
+        best = best.min(run.elapsed());
+        hint::black_box(value.expect("benchmark scripts succeed"));
+        runs += 1;
+    }
+    (best, runs)
+}
+
+fn main() {
+    /* cargo passes --bench, and any arguments after -- */
+    let filters: Vec<String> = env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with('-'))
+        .collect();
+    for (name, script) in BENCHES {
+        if !filters.is_empty() && !filters.iter().any(|filter| name.contains(filter.as_str())) {
+            continue;
+        }
+        for (label, evaluator) in [
+            ("tree", Evaluator::TreeWalker),
+            ("bytecode", Evaluator::Bytecode),
+        ] {
+            let (best, runs) = time(evaluator, script);
+            println!(
+                "{:<6} {:<9} {:>10.3} ms  (best of {})",
+                name,
+                label,
+                best.as_secs_f64() * 1e3,
+                runs
+            );
+        }
+    }
+}
@@ -0,0 +1,330 @@
+//! Maps and sets, and the builtins operating on them.
+//!
+//! `[k v ...]` builds a map and `#{x ...}` a set. Keys, and the elements of
+//! sets, are numbers, booleans, strings, symbols or Q-expressions of these.
+//! They are compared structurally, as [`LispValue`]'s `PartialEq` does, so
+//! the integer `1` and the float `1.0` are distinct keys. Maps and sets
+//! keep their keys in order, so they print the same each time.
+
+use crate::{
+    env::Env,
+    number::Number,
+    registry::{arg, Def, ANY, MAP},
+    value::{lval_bool, lval_err, lval_err_arg, lval_err_type, lval_sexpr, LispValue, Tag},
+};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    fmt, mem,
+    rc::Rc,
+};
+
+/// A key of a map or an element of a set: a value that can be ordered.
+#[derive(Clone, Debug)]
+pub struct Key(LispValue);
+
+impl Key {
+    /// `x` as a key, or `None` if it is not a number, boolean, string,
+    /// symbol or Q-expression of these.
+    pub fn new(x: LispValue) -> Option<Key> {
+        is_key(&x).then(|| Key(x.with_span(None)))
+    }
+
+    /// The value of the key.
+    pub fn value(&self) -> &LispValue {
+        &self.0
+    }
+}
+
+fn is_key(x: &LispValue) -> bool {
+    match x {
+        LispValue::Num(..) | LispValue::Bool(..) | LispValue::Str(..) | LispValue::Sym(..) => true,
+        LispValue::Qexpr(cells, _) => cells.iter().all(is_key),
+        _ => false,
+    }
+}
+
+/* The order of the kinds of keys */
+fn key_rank(x: &LispValue) -> u8 {
+    match x {
+        LispValue::Bool(..) => 0,
+        LispValue::Num(..) => 1,
+        LispValue::Str(..) => 2,
+        LispValue::Sym(..) => 3,
+        _ => 4,
+    }
+}
+
+/* Order numbers by value, as floats, then exact numbers before floats, then
+exact numbers exactly. Equal only if the numbers are structurally equal */
+fn key_cmp_num(x: &Number, y: &Number) -> Ordering {
+    x.to_f64()
+        .total_cmp(&y.to_f64())
+        .then_with(|| match (x, y) {
+            (Number::Float(_), Number::Float(_)) => Ordering::Equal,
+            (Number::Float(_), _) => Ordering::Greater,
+            (_, Number::Float(_)) => Ordering::Less,
+            (x, y) => x.compare(y).expect("exact numbers are ordered"),
+        })
+}
+
+fn key_cmp(x: &LispValue, y: &LispValue) -> Ordering {
+    match (x, y) {
+        (LispValue::Bool(x, _), LispValue::Bool(y, _)) => x.cmp(y),
+        (LispValue::Num(x, _), LispValue::Num(y, _)) => key_cmp_num(x, y),
+        (LispValue::Str(x, _), LispValue::Str(y, _)) => x.cmp(y),
+        (LispValue::Sym(x, _), LispValue::Sym(y, _)) => x.cmp(y),
+        (LispValue::Qexpr(x, _), LispValue::Qexpr(y, _)) => x
+            .iter()
+            .zip(y.iter())
+            .map(|(x, y)| key_cmp(x, y))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| x.len().cmp(&y.len())),
+        (x, y) => key_rank(x).cmp(&key_rank(y)),
+    }
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Self) -> Ordering {
+        key_cmp(&self.0, &other.0)
+    }
+}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Key {}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/* The argument at position `arg` (starting at 1) of `func` as a key */
+fn lval_key(func: &str, x: &LispValue, arg: usize) -> Result<Key, LispValue> {
+    Key::new(x.clone()).ok_or_else(|| {
+        lval_err_arg(
+            arg,
+            format!("Function '{}' cannot use {} {} as a key.", func, x.tag(), x),
+        )
+    })
+}
+
+/* The keys in the arguments of `func` from position `from` on */
+fn lval_keys(func: &str, a: &LispValue, from: usize) -> Result<Vec<Key>, LispValue> {
+    a.cells()[from - 1..]
+        .iter()
+        .enumerate()
+        .map(|(i, x)| lval_key(func, x, from + i))
+        .collect()
+}
+
+/* Take the first argument out of a, to change it if it is not shared,
+leaving () in its place so that the others keep their positions */
+fn lval_take_first(a: &mut LispValue) -> LispValue {
+    mem::replace(&mut a.cells_mut()[0], lval_sexpr())
+}
+
+/* Insert the key and value pairs from position `from` on into map */
+fn lval_insert_pairs(
+    func: &str,
+    map: &mut BTreeMap<Key, LispValue>,
+    a: &LispValue,
+    from: usize,
+) -> Result<(), LispValue> {
+    let pairs = &a.cells()[from - 1..];
+    if !pairs.len().is_multiple_of(2) {
+        return Err(lval_err(format!(
+            "Function '{}' passed a key with no value.",
+            func
+        )));
+    }
+    for (i, pair) in pairs.chunks(2).enumerate() {
+        let key = lval_key(func, &pair[0], from + 2 * i)?;
+        map.insert(key, pair[1].clone());
+    }
+    Ok(())
+}
+
+/* hash-map k v ...: the map from each k to its v, the last one if a key
+is repeated */
+fn builtin_hash_map(_e: &Env, a: LispValue) -> LispValue {
+    let mut map = BTreeMap::new();
+    match lval_insert_pairs("hash-map", &mut map, &a, 1) {
+        Ok(()) => LispValue::Map(Rc::new(map)),
+        Err(err) => err,
+    }
+}
+
+/* hash-set x ...: the set of the xs */
+fn builtin_hash_set(_e: &Env, a: LispValue) -> LispValue {
+    match lval_keys("hash-set", &a, 1) {
+        Ok(keys) => LispValue::Set(Rc::new(keys.into_iter().collect())),
+        Err(err) => err,
+    }
+}
+
+/* get m k [default]: the value of k in the map m, or the element k of the
+set m, or default if there is none */
+fn builtin_get(_e: &Env, a: LispValue) -> LispValue {
+    let key = match lval_key("get", &a.cells()[1], 2) {
+        Ok(key) => key,
+        Err(err) => return err,
+    };
+    let found = match &a.cells()[0] {
+        LispValue::Map(map) => map.get(&key).cloned(),
+        LispValue::Set(set) => set.get(&key).map(|key| key.value().clone()),
+        _ => unreachable!("checked by the registry"),
+    };
+    match (found, a.cells().get(2)) {
+        (Some(x), _) => x,
+        (None, Some(default)) => default.clone(),
+        (None, None) => lval_err_arg(
+            2,
+            format!("Function 'get' passed key {} that is not there.", key),
+        ),
+    }
+}
+
+/* assoc m k v ...: m with each k mapped to its v */
+fn builtin_assoc(_e: &Env, mut a: LispValue) -> LispValue {
+    let LispValue::Map(mut map) = lval_take_first(&mut a) else {
+        unreachable!("checked by the registry");
+    };
+    match lval_insert_pairs("assoc", Rc::make_mut(&mut map), &a, 2) {
+        Ok(()) => LispValue::Map(map),
+        Err(err) => err,
+    }
+}
+
+/* dissoc m k ...: m without the keys, or elements, k */
+fn builtin_dissoc(_e: &Env, mut a: LispValue) -> LispValue {
+    let keys = match lval_keys("dissoc", &a, 2) {
+        Ok(keys) => keys,
+        Err(err) => return err,
+    };
+    match lval_take_first(&mut a) {
+        LispValue::Map(mut map) => {
+            for key in &keys {
+                Rc::make_mut(&mut map).remove(key);
+            }
+            LispValue::Map(map)
+        }
+        LispValue::Set(mut set) => {
+            for key in &keys {
+                Rc::make_mut(&mut set).remove(key);
+            }
+            LispValue::Set(set)
+        }
+        _ => unreachable!("checked by the registry"),
+    }
+}
+
+/* keys m: the keys of the map m, or the elements of the set m, in order */
+fn builtin_keys(_e: &Env, a: LispValue) -> LispValue {
+    let keys: Vec<LispValue> = match &a.cells()[0] {
+        LispValue::Map(map) => map.keys().map(|key| key.value().clone()).collect(),
+        LispValue::Set(set) => set.iter().map(|key| key.value().clone()).collect(),
+        _ => unreachable!("checked by the registry"),
+    };
+    LispValue::Qexpr(Rc::new(keys), None)
+}
+
+/* vals m: the values of the map m, in the order of their keys */
+fn builtin_vals(_e: &Env, a: LispValue) -> LispValue {
+    let LispValue::Map(map) = &a.cells()[0] else {
+        unreachable!("checked by the registry");
+    };
+    LispValue::Qexpr(Rc::new(map.values().cloned().collect()), None)
+}
+
+/* has? m k: whether the map m has the key k, or the set m the element k */
+fn builtin_has(_e: &Env, a: LispValue) -> LispValue {
+    let key = match lval_key("has?", &a.cells()[1], 2) {
+        Ok(key) => key,
+        Err(err) => return err,
+    };
+    match &a.cells()[0] {
+        LispValue::Map(map) => lval_bool(map.contains_key(&key)),
+        LispValue::Set(set) => lval_bool(set.contains(&key)),
+        _ => unreachable!("checked by the registry"),
+    }
+}
+
+/* Fold the maps, or sets, passed to `func` with map_op, or set_op */
+fn builtin_set_op(
+    mut a: LispValue,
+    func: &str,
+    map_op: fn(&mut BTreeMap<Key, LispValue>, &BTreeMap<Key, LispValue>),
+    set_op: fn(&mut BTreeSet<Key>, &BTreeSet<Key>),
+) -> LispValue {
+    let mut x = lval_take_first(&mut a);
+    for (i, y) in a.cells().iter().enumerate().skip(1) {
+        match (&mut x, y) {
+            (LispValue::Map(x), LispValue::Map(y)) => map_op(Rc::make_mut(x), y),
+            (LispValue::Set(x), LispValue::Set(y)) => set_op(Rc::make_mut(x), y),
+            /* A set among maps, or a map among sets */
+            (x, y) => return lval_err_type(func, i + 1, y.tag(), x.tag()),
+        }
+    }
+    x
+}
+
+/* union m ...: the keys in any of the maps, with their values from the last
+map that has them, or the elements in any of the sets */
+fn builtin_union(_e: &Env, a: LispValue) -> LispValue {
+    builtin_set_op(
+        a,
+        "union",
+        |x, y| x.extend(y.iter().map(|(k, v)| (k.clone(), v.clone()))),
+        |x, y| x.extend(y.iter().cloned()),
+    )
+}
+
+/* intersection m ...: the keys in all of the maps, with their values from
+the first, or the elements in all of the sets */
+fn builtin_intersection(_e: &Env, a: LispValue) -> LispValue {
+    builtin_set_op(
+        a,
+        "intersection",
+        |x, y| x.retain(|k, _| y.contains_key(k)),
+        |x, y| x.retain(|k| y.contains(k)),
+    )
+}
+
+/* Maps or sets, for the builtins taking either */
+const COLLECTION: &[Tag] = &[Tag::Map, Tag::Set];
+
+pub(crate) static COLLECTION_BUILTINS: &[Def] = &[
+    Def::builtin("hash-map", &[], builtin_hash_map).rest(arg("pairs", ANY)),
+    Def::builtin("hash-set", &[], builtin_hash_set).rest(arg("xs", ANY)),
+    Def::builtin("get", &[arg("m", COLLECTION), arg("k", ANY)], builtin_get)
+        .optional(&[arg("default", ANY)]),
+    Def::builtin(
+        "assoc",
+        &[arg("m", MAP), arg("k", ANY), arg("v", ANY)],
+        builtin_assoc,
+    )
+    .rest(arg("pairs", ANY)),
+    Def::builtin("dissoc", &[arg("m", COLLECTION)], builtin_dissoc).rest(arg("ks", ANY)),
+    Def::builtin("keys", &[arg("m", COLLECTION)], builtin_keys),
+    Def::builtin("vals", &[arg("m", MAP)], builtin_vals),
+    Def::builtin("has?", &[arg("m", COLLECTION), arg("k", ANY)], builtin_has),
+    Def::builtin("union", &[arg("m", COLLECTION)], builtin_union).rest(arg("ms", COLLECTION)),
+    Def::builtin(
+        "intersection",
+        &[arg("m", COLLECTION)],
+        builtin_intersection,
+    )
+    .rest(arg("ms", COLLECTION)),
+];
@@ -0,0 +1,407 @@
+//! A compiler from read expressions to bytecode for the [`vm`](crate::vm).
+//!
+//! An expression compiles to a [`Chunk`]: a sequence of [`Op`]s for a stack
+//! machine, the constants they push and the calls they make. Symbols are
+//! still looked up by name when the code runs, since `def` and `=` may bind
+//! them at any time, and what the first element of a call turns out to be
+//! decides how the call is made:
+//!
+//! ```text
+//! (f x 1)     Function f, Head, Load x, Const 1, Call
+//! ```
+//!
+//! `Head` hands a special form or macro the unevaluated arguments, as the
+//! tree-walking evaluator does, and skips to the end of the call. The
+//! special forms `if`, `when`, `unless`, `do` and `let` are compiled inline
+//! instead, so their conditions and bodies need no compiling at each call,
+//! guarded by an `Inline` check that the symbol is still bound to that form.
+
+use crate::{
+    forms::SPECIAL_FORMS,
+    registry::Def,
+    span::Span,
+    value::{lval_sexpr, LispValue},
+};
+use std::{cell::OnceCell, fmt, rc::Rc};
+
+/// An instruction. Targets are indices into the code of the chunk, and
+/// calls are indices into its calls.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Op {
+    /// Push a copy of a constant.
+    Const(usize),
+    /// Push the value of the symbol constant, or an error naming it.
+    Load(usize),
+    /// Push the value of the symbol constant, the first element of a call
+    /// with arguments, or an error that it is an unknown function.
+    Function(usize),
+    /// With the first element of a call on top: if it is a special form or
+    /// a macro, pop it, apply it to the unevaluated arguments and continue
+    /// at `end`. Otherwise go on to evaluate the arguments.
+    Head {
+        call: usize,
+        end: usize,
+        tail: bool,
+    },
+    /// Pop the arguments and the first element of a call, and apply it.
+    Call {
+        call: usize,
+        tail: bool,
+    },
+    /// If the special form declared by `def` is on top, pop it and go on
+    /// with its code compiled inline. Otherwise continue at `generic`, an
+    /// ordinary call, even if what is on top is another form of that name.
+    Inline {
+        def: &'static Def,
+        generic: usize,
+    },
+    /// Pop the condition of the inline form `func`, and go on if it is
+    /// `when` or else continue at `skip`. Anything but a boolean is left as
+    /// an error, continuing at `end`.
+    Test {
+        func: &'static str,
+        call: usize,
+        when: bool,
+        skip: usize,
+        end: usize,
+    },
+    /// Pop the value of a body that is not the last one, unless it is an
+    /// error: then leave it and continue at `end`.
+    Discard {
+        call: usize,
+        end: usize,
+    },
+    /// Evaluate what follows in a new scope on top of the current one.
+    EnterScope,
+    /// Pop a value and bind the symbol constant to it in the current scope,
+    /// unless it is an error: then leave it and continue at `end`.
+    Bind {
+        sym: usize,
+        call: usize,
+        end: usize,
+    },
+    /// Go back to the scope before the last `EnterScope`.
+    LeaveScope,
+    Jump(usize),
+    /// Pop the value of the chunk and return it.
+    Return,
+}
+
+impl Op {
+    /* Map the labels the compiler jumps to to where they were placed */
+    fn relabel(&mut self, labels: &[usize]) {
+        match self {
+            Op::Head { end, .. } | Op::Discard { end, .. } | Op::Bind { end, .. } => {
+                *end = labels[*end]
+            }
+            Op::Inline { generic, .. } => *generic = labels[*generic],
+            Op::Test { skip, end, .. } => {
+                *skip = labels[*skip];
+                *end = labels[*end];
+            }
+            Op::Jump(target) => *target = labels[*target],
+            _ => {}
+        }
+    }
+}
+
+/// A call in the source: its arguments as read, for special forms and
+/// macros, and where it and each of its elements were read from, to
+/// locate errors.
+#[derive(Debug)]
+pub(crate) struct Call {
+    pub(crate) args: Rc<Vec<LispValue>>,
+    pub(crate) span: Option<Span>,
+    pub(crate) spans: Vec<Option<Span>>,
+}
+
+/// The bytecode of an expression.
+#[derive(Debug, Default)]
+pub(crate) struct Chunk {
+    pub(crate) code: Vec<Op>,
+    pub(crate) consts: Vec<LispValue>,
+    pub(crate) calls: Vec<Call>,
+}
+
+/// The bytecode of a lambda's body, compiled when first needed and shared
+/// by all copies of the lambda. Never affects equality.
+#[derive(Clone, Default)]
+pub(crate) struct Code(Rc<OnceCell<Rc<Chunk>>>);
+
+impl Code {
+    /// The chunk, compiling it from `body` if it is not yet.
+    pub(crate) fn get_or_compile(&self, body: &LispValue) -> Rc<Chunk> {
+        self.0
+            .get_or_init(|| Rc::new(compile_body(body.clone())))
+            .clone()
+    }
+}
+
+impl PartialEq for Code {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Code {}
+
+impl fmt::Debug for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.get() {
+            Some(chunk) => write!(f, "<{} ops>", chunk.code.len()),
+            None => write!(f, "<not compiled>"),
+        }
+    }
+}
+
+/// Compile the expression `x`.
+pub(crate) fn compile(x: LispValue) -> Chunk {
+    let mut compiler = Compiler::default();
+    compiler.expr(x, true);
+    compiler.finish()
+}
+
+/// Compile the body `x`, a Q-expression as the S-expression it holds, as
+/// for lambdas.
+pub(crate) fn compile_body(x: LispValue) -> Chunk {
+    let mut compiler = Compiler::default();
+    compiler.body(x, true);
+    compiler.finish()
+}
+
+#[derive(Default)]
+struct Compiler {
+    chunk: Chunk,
+    /* Where each label was placed */
+    labels: Vec<usize>,
+}
+
+/* A special form whose code is compiled inline, with the arguments it was
+checked to have */
+enum Inline {
+    If,
+    When(bool),
+    Do,
+    Let,
+}
+
+impl Compiler {
+    fn finish(mut self) -> Chunk {
+        self.emit(Op::Return);
+        for op in &mut self.chunk.code {
+            op.relabel(&self.labels);
+        }
+        self.chunk
+    }
+
+    fn emit(&mut self, op: Op) {
+        self.chunk.code.push(op);
+    }
+
+    fn constant(&mut self, x: LispValue) -> usize {
+        self.chunk.consts.push(x);
+        self.chunk.consts.len() - 1
+    }
+
+    fn label(&mut self) -> usize {
+        self.labels.push(usize::MAX);
+        self.labels.len() - 1
+    }
+
+    fn place(&mut self, label: usize) {
+        self.labels[label] = self.chunk.code.len();
+    }
+
+    /* An expression, in tail position or not */
+    fn expr(&mut self, x: LispValue, tail: bool) {
+        match x {
+            LispValue::Sym(..) => {
+                let sym = self.constant(x);
+                self.emit(Op::Load(sym));
+            }
+            LispValue::Sexpr(cells, span) => self.sexpr(Rc::unwrap_or_clone(cells), span, tail),
+            x => {
+                let x = self.constant(x);
+                self.emit(Op::Const(x));
+            }
+        }
+    }
+
+    /* A body, a Q-expression as the S-expression it holds */
+    fn body(&mut self, x: LispValue, tail: bool) {
+        match x {
+            LispValue::Qexpr(cells, span) => self.sexpr(Rc::unwrap_or_clone(cells), span, tail),
+            x => self.expr(x, tail),
+        }
+    }
+
+    /* Bodies in order, stopping at the first error. All but the last are
+    discarded, and there is () if there are none */
+    fn bodies(&mut self, mut bodies: Vec<LispValue>, call: usize, tail: bool, end: usize) {
+        let Some(last) = bodies.pop() else {
+            let x = self.constant(lval_sexpr());
+            self.emit(Op::Const(x));
+            return;
+        };
+        for body in bodies {
+            self.body(body, false);
+            self.emit(Op::Discard { call, end });
+        }
+        self.body(last, tail);
+    }
+
+    fn sexpr(&mut self, mut cells: Vec<LispValue>, span: Option<Span>, tail: bool) {
+        /* Empty Expression */
+        if cells.is_empty() {
+            let x = self.constant(LispValue::Sexpr(Rc::new(cells), span));
+            self.emit(Op::Const(x));
+            return;
+        }
+
+        let spans = cells.iter().map(|cell| cell.span().cloned()).collect();
+        let head = cells.remove(0);
+        let inline = match &head {
+            LispValue::Sym(sym, _) => inline_form(sym, &cells),
+            _ => None,
+        };
+        let call = self.chunk.calls.len();
+        self.chunk.calls.push(Call {
+            args: Rc::new(cells.clone()),
+            span,
+            spans,
+        });
+        let end = self.label();
+        match head {
+            LispValue::Sym(..) if !cells.is_empty() => {
+                let sym = self.constant(head);
+                self.emit(Op::Function(sym));
+            }
+            head => self.expr(head, false),
+        }
+
+        if let Some((name, form)) = inline {
+            let generic = self.label();
+            self.emit(Op::Inline {
+                def: special_form(name),
+                generic,
+            });
+            self.inline(form, cells.clone(), call, tail, end);
+            self.emit(Op::Jump(end));
+            self.place(generic);
+        }
+
+        self.emit(Op::Head { call, end, tail });
+        for cell in cells {
+            self.expr(cell, false);
+        }
+        self.emit(Op::Call { call, tail });
+        self.place(end);
+    }
+
+    /* The code of a special form, as its function in forms.rs evaluates it,
+    leaving its value on the stack */
+    fn inline(
+        &mut self,
+        form: Inline,
+        mut args: Vec<LispValue>,
+        call: usize,
+        tail: bool,
+        end: usize,
+    ) {
+        match form {
+            Inline::If => {
+                let skip = self.label();
+                let test = args.remove(0);
+                self.expr(test, false);
+                self.emit(Op::Test {
+                    func: "if",
+                    call,
+                    when: true,
+                    skip,
+                    end,
+                });
+                self.body(args.remove(0), tail);
+                self.emit(Op::Jump(end));
+                self.place(skip);
+                match args.pop() {
+                    Some(otherwise) => self.body(otherwise, tail),
+                    None => {
+                        let x = self.constant(lval_sexpr());
+                        self.emit(Op::Const(x));
+                    }
+                }
+            }
+            Inline::When(when) => {
+                let skip = self.label();
+                let test = args.remove(0);
+                self.expr(test, false);
+                self.emit(Op::Test {
+                    func: if when { "when" } else { "unless" },
+                    call,
+                    when,
+                    skip,
+                    end,
+                });
+                self.bodies(args, call, tail, end);
+                self.emit(Op::Jump(end));
+                self.place(skip);
+                let x = self.constant(lval_sexpr());
+                self.emit(Op::Const(x));
+            }
+            Inline::Do => self.bodies(args, call, tail, end),
+            Inline::Let => {
+                let LispValue::Qexpr(bindings, _) = args.remove(0) else {
+                    unreachable!("checked by inline_form");
+                };
+                let leave = self.label();
+                self.emit(Op::EnterScope);
+                let mut bindings = Rc::unwrap_or_clone(bindings).into_iter();
+                while let (Some(sym), Some(value)) = (bindings.next(), bindings.next()) {
+                    self.expr(value, false);
+                    let sym = self.constant(sym);
+                    self.emit(Op::Bind {
+                        sym,
+                        call,
+                        end: leave,
+                    });
+                }
+                self.bodies(args, call, tail, leave);
+                self.place(leave);
+                self.emit(Op::LeaveScope);
+            }
+        }
+    }
+}
+
+/* The registry's declaration of the special form `name` */
+fn special_form(name: &str) -> &'static Def {
+    SPECIAL_FORMS
+        .iter()
+        .find(|def| def.name == name)
+        .expect("inline forms are declared")
+}
+
+/* Which special form named sym a call with the arguments args can be
+compiled inline as. Calls the form would reject are left to it, for its
+errors */
+fn inline_form(sym: &str, args: &[LispValue]) -> Option<(&'static str, Inline)> {
+    match sym {
+        "if" if (2..=3).contains(&args.len()) => Some(("if", Inline::If)),
+        "when" if !args.is_empty() => Some(("when", Inline::When(true))),
+        "unless" if !args.is_empty() => Some(("unless", Inline::When(false))),
+        "do" => Some(("do", Inline::Do)),
+        "let" => match args.first() {
+            Some(LispValue::Qexpr(bindings, _))
+                if bindings.len() % 2 == 0
+                    && bindings
+                        .iter()
+                        .step_by(2)
+                        .all(|sym| matches!(sym, LispValue::Sym(..))) =>
+            {
+                Some(("let", Inline::Let))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
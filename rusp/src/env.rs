@@ -0,0 +1,194 @@
+//! Environments binding symbols to values.
+
+use crate::{
+    gc::{self, Heap},
+    symbol::{Symbol, SymbolMap},
+    value::{lval_err, LispValue},
+    vm::Evaluator,
+};
+use std::{
+    cell::{Cell, RefCell},
+    fmt,
+    rc::Rc,
+};
+
+/// How deeply evaluations may nest by default, see [`Env::enter`]: as deep
+/// as fits in the 8 MiB stack main threads commonly have, at
+/// [`STACK_PER_DEPTH`] per level.
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+/// The native stack to allow for each level of nesting, enough even in
+/// debug builds: a thread evaluating at most `n` deep needs `n` times this.
+pub const STACK_PER_DEPTH: usize = 8 << 10;
+
+/// A handle to a scope. Clones share the same bindings.
+///
+/// Scopes are reference counted, and a lambda bound in the scope it closes
+/// over makes a cycle: the heap shared by a global environment and the
+/// scopes on top of it finds and reclaims those, see
+/// [`Env::collect_garbage`].
+#[derive(Clone)]
+pub struct Env(pub(crate) Rc<RefCell<Scope>>);
+
+#[derive(Default)]
+pub(crate) struct Scope {
+    pub(crate) parent: Option<Env>,
+    pub(crate) vars: SymbolMap<LispValue>,
+    global: Rc<Global>,
+}
+
+/* What a global environment and all scopes created on top of it share */
+#[derive(Default)]
+struct Global {
+    depth: Depth,
+    heap: Heap,
+    evaluator: Cell<Evaluator>,
+}
+
+/* The nesting of evaluations */
+struct Depth {
+    current: Cell<usize>,
+    max: Cell<usize>,
+}
+
+impl Default for Depth {
+    fn default() -> Self {
+        Self {
+            current: Cell::new(0),
+            max: Cell::new(DEFAULT_MAX_DEPTH),
+        }
+    }
+}
+
+/// Proof of one level of evaluation depth, given back when dropped.
+pub(crate) struct DepthGuard(Rc<Global>);
+
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        let depth = &self.0.depth;
+        depth.current.set(depth.current.get() - 1);
+    }
+}
+
+impl Default for Env {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Env {
+    /// Create an empty environment.
+    pub fn new() -> Self {
+        let env = Self(Rc::default());
+        let global = env.0.borrow().global.clone();
+        global.heap.track(&env);
+        env
+    }
+
+    /// Create an empty scope whose lookups fall back to `parent`.
+    pub fn with_parent(parent: &Env) -> Self {
+        let global = parent.0.borrow().global.clone();
+        let env = Self(Rc::new(RefCell::new(Scope {
+            parent: Some(parent.clone()),
+            vars: SymbolMap::default(),
+            global: global.clone(),
+        })));
+        global.heap.track(&env);
+        env
+    }
+
+    /// Reclaim the scopes that are only kept alive by cycles, such as a
+    /// scope binding a lambda that closes over it, returning how many there
+    /// were. Scopes in use, including by values outside the environment,
+    /// are never reclaimed.
+    ///
+    /// This happens by itself as scopes are created; call it to reclaim
+    /// them sooner.
+    pub fn collect_garbage(&self) -> usize {
+        let global = self.0.borrow().global.clone();
+        gc::collect(&global.heap)
+    }
+
+    /// Enter one more level of nested evaluation, or return `None` if that
+    /// would nest deeper than the maximum depth.
+    pub(crate) fn enter(&self) -> Option<DepthGuard> {
+        let global = self.0.borrow().global.clone();
+        let depth = &global.depth;
+        if depth.current.get() >= depth.max.get() {
+            return None;
+        }
+        depth.current.set(depth.current.get() + 1);
+        Some(DepthGuard(global))
+    }
+
+    /// The maximum nesting depth of evaluations in this environment.
+    pub fn max_depth(&self) -> usize {
+        self.0.borrow().global.depth.max.get()
+    }
+
+    /// Limit how deeply evaluations may nest in this environment, and all
+    /// scopes sharing its global environment.
+    pub fn set_max_depth(&self, max: usize) {
+        self.0.borrow().global.depth.max.set(max);
+    }
+
+    /// How expressions are evaluated in this environment.
+    pub fn evaluator(&self) -> Evaluator {
+        self.0.borrow().global.evaluator.get()
+    }
+
+    /// Choose how expressions are evaluated in this environment, and all
+    /// scopes sharing its global environment.
+    pub fn set_evaluator(&self, evaluator: Evaluator) {
+        self.0.borrow().global.evaluator.set(evaluator);
+    }
+
+    /// Look up `k` here and then in the parents, returning a copy of its
+    /// value or an error naming it.
+    pub fn get(&self, k: impl Into<Symbol>) -> LispValue {
+        let k = k.into();
+        /* If no symbol found return error */
+        self.lookup(k)
+            .unwrap_or_else(|| lval_err(format!("Unbound Symbol '{}'", k)))
+    }
+
+    /// Look up `k` here and then in the parents, returning a copy of its
+    /// value if it is bound.
+    pub fn lookup(&self, k: impl Into<Symbol>) -> Option<LispValue> {
+        let k = k.into();
+        let scope = self.0.borrow();
+        match (scope.vars.get(&k), &scope.parent) {
+            (Some(v), _) => Some(v.clone()),
+            (None, Some(parent)) => parent.lookup(k),
+            (None, None) => None,
+        }
+    }
+
+    /// Bind `k` to `v` in this scope, replacing any existing binding.
+    pub fn put(&self, k: impl Into<Symbol>, v: LispValue) {
+        self.0.borrow_mut().vars.insert(k.into(), v);
+    }
+
+    /// Bind `k` to `v` in the outermost (global) scope.
+    pub fn def(&self, k: impl Into<Symbol>, v: LispValue) {
+        let parent = self.0.borrow().parent.clone();
+        match parent {
+            Some(parent) => parent.def(k, v),
+            None => self.put(k, v),
+        }
+    }
+}
+
+impl PartialEq for Env {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Env {}
+
+impl fmt::Debug for Env {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Env({:p})", Rc::as_ptr(&self.0))
+    }
+}
@@ -0,0 +1,519 @@
+//! Evaluation of S-expressions and the builtin functions.
+
+use crate::{
+    env::Env,
+    macros::lval_expand,
+    reader::lval_read,
+    registry::{arg, Def, ANY, LIST, STR},
+    span::Span,
+    symbol::{Symbol, AMPERSAND},
+    value::{
+        lval_add, lval_err, lval_err_arg, lval_err_type, lval_join, lval_lambda, lval_pop,
+        lval_qexpr, lval_sexpr, lval_take, Lambda, LispValue, Step, Tag,
+    },
+    vm::{self, Evaluator},
+};
+use std::{fs, rc::Rc};
+
+fn builtin_head(_e: &Env, a: LispValue) -> LispValue {
+    /* Check error conditions */
+    if a.cells()[0].count() == 0 {
+        return lval_err_arg(1, "Function 'head' passed {} for argument 1.");
+    }
+
+    /* Take first argument */
+    let v = lval_take(a, 0);
+
+    /* Keep only its first element, copying no others */
+    let span = v.span().cloned();
+    lval_add(lval_qexpr(), lval_take(v, 0)).with_span(span)
+}
+
+fn builtin_tail(_e: &Env, a: LispValue) -> LispValue {
+    /* Check error conditions */
+    if a.cells()[0].count() == 0 {
+        return lval_err_arg(1, "Function 'tail' passed {} for argument 1.");
+    }
+    /* Take first argument */
+    let mut v = lval_take(a, 0);
+
+    /* Delete first element and return */
+    lval_pop(&mut v, 0);
+    v
+}
+
+fn builtin_list(_e: &Env, a: LispValue) -> LispValue {
+    let LispValue::Sexpr(cells, span) = a else {
+        return a;
+    };
+    LispValue::Qexpr(cells, span)
+}
+
+/* eval is a special form, to evaluate the expression in tail position */
+fn special_eval(e: &Env, a: LispValue) -> Step {
+    match lval_eval(e, lval_take(a, 0)) {
+        LispValue::Qexpr(cells, span) => Step::Eval(e.clone(), LispValue::Sexpr(cells, span)),
+        err @ LispValue::Err(_) => Step::Value(err),
+        other => Step::Value(lval_err_type("eval", 1, other.tag(), Tag::Qexpr)),
+    }
+}
+
+fn builtin_join(_e: &Env, mut a: LispValue) -> LispValue {
+    let mut x = lval_pop(&mut a, 0);
+
+    while a.count() != 0 {
+        x = lval_join(x, lval_pop(&mut a, 0));
+    }
+
+    x
+}
+
+/* Collect the names in a Q-expression of symbols, for `func` */
+fn lval_formals(func: &str, syms: &LispValue) -> Result<Vec<Symbol>, LispValue> {
+    syms.cells()
+        .iter()
+        .map(|sym| match sym {
+            LispValue::Sym(name, _) => Ok(*name),
+            other => Err(lval_err(format!(
+                "Function '{}' cannot define non-symbol. Got {}, Expected {}.",
+                func,
+                other.tag(),
+                Tag::Sym
+            ))
+            .with_span(other.span().cloned())),
+        })
+        .collect()
+}
+
+/* def and =: bind each symbol in the first argument to the value after it,
+in the global scope if global is set, else in the local one */
+fn builtin_var(e: &Env, mut a: LispValue, func: &str, global: bool) -> LispValue {
+    /* First argument is symbol list */
+    let syms = lval_pop(&mut a, 0);
+
+    /* Ensure all elements of first list are symbols */
+    let names = match lval_formals(func, &syms) {
+        Ok(names) => names,
+        Err(err) => return err,
+    };
+
+    /* Check there is a value for each symbol */
+    if names.len() != a.count() {
+        return lval_err(format!(
+            "Function '{}' passed too {} values for symbols. Got {}, Expected {}.",
+            func,
+            if a.count() < names.len() {
+                "few"
+            } else {
+                "many"
+            },
+            a.count(),
+            names.len()
+        ));
+    }
+
+    /* Assign copies of values to symbols */
+    for (name, value) in names.into_iter().zip(a.cells_mut().drain(..)) {
+        if global {
+            e.def(name, value);
+        } else {
+            e.put(name, value);
+        }
+    }
+
+    lval_sexpr()
+}
+
+fn builtin_def(e: &Env, a: LispValue) -> LispValue {
+    builtin_var(e, a, "def", true)
+}
+
+fn builtin_put(e: &Env, a: LispValue) -> LispValue {
+    builtin_var(e, a, "=", false)
+}
+
+/* Check that `&` is only ever followed by exactly one formal */
+fn lval_check_variadic(formals: &[Symbol]) -> Result<(), LispValue> {
+    match formals.iter().position(|sym| *sym == AMPERSAND) {
+        Some(i) if i + 2 != formals.len() => Err(lval_err(
+            "Function format invalid. Symbol '&' not followed by single symbol.",
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn builtin_lambda(e: &Env, mut a: LispValue) -> LispValue {
+    /* Check first Q-Expression contains only Symbols */
+    let formals = match lval_formals("\\", &lval_pop(&mut a, 0)) {
+        Ok(formals) => formals,
+        Err(err) => return err,
+    };
+    if let Err(err) = lval_check_variadic(&formals) {
+        return err;
+    }
+
+    lval_lambda(e, formals, lval_take(a, 0))
+}
+
+/// Split the two Q-expression arguments `{name formals...} {body}` of
+/// `func`, a definer like `fun`, into the name, formals and body.
+pub(crate) fn lval_definition(
+    func: &str,
+    mut a: LispValue,
+) -> Result<(Symbol, Vec<Symbol>, LispValue), LispValue> {
+    let mut formals = lval_formals(func, &lval_pop(&mut a, 0))?;
+    if formals.is_empty() {
+        return Err(lval_err_arg(
+            1,
+            format!("Function '{}' passed {{}} for argument 1.", func),
+        ));
+    }
+    let name = formals.remove(0);
+    lval_check_variadic(&formals)?;
+    Ok((name, formals, lval_take(a, 0)))
+}
+
+fn builtin_fun(e: &Env, a: LispValue) -> LispValue {
+    /* fun {name formals...} {body} is def {name} (\ {formals...} {body}) */
+    match lval_definition("fun", a) {
+        Ok((name, formals, body)) => {
+            e.def(name, lval_lambda(e, formals, body));
+            lval_sexpr()
+        }
+        Err(err) => err,
+    }
+}
+
+/// Evaluate each expression in the S-expression `exprs` in order, returning
+/// the value of the last one or the first error.
+pub(crate) fn lval_eval_all(e: &Env, mut exprs: LispValue) -> LispValue {
+    let mut x = lval_sexpr();
+    while exprs.count() > 0 {
+        x = lval_eval(e, lval_pop(&mut exprs, 0));
+        if x.tag() == Tag::Err {
+            break;
+        }
+    }
+    x
+}
+
+/// Read the file at `path` and evaluate its expressions in `e`.
+pub(crate) fn lval_load(e: &Env, path: &str) -> LispValue {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) => return lval_err(format!("Could not load Library '{}': {}", path, err)),
+    };
+    match lval_read(path, &text) {
+        Ok(exprs) => lval_eval_all(e, exprs),
+        Err(err) => LispValue::Err(Box::new(err.into())),
+    }
+}
+
+fn builtin_load(e: &Env, a: LispValue) -> LispValue {
+    let LispValue::Str(path, _) = &a.cells()[0] else {
+        unreachable!("checked by the registry");
+    };
+
+    match lval_load(e, path) {
+        err @ LispValue::Err(_) => err,
+        _ => lval_sexpr(),
+    }
+}
+
+fn builtin_print(_e: &Env, a: LispValue) -> LispValue {
+    /* Print each argument separated by a space, then a newline. Strings are
+    printed without quotes or escapes */
+    let line: Vec<String> = a
+        .cells()
+        .iter()
+        .map(|cell| match cell {
+            LispValue::Str(s, _) => s.clone(),
+            other => other.to_string(),
+        })
+        .collect();
+    println!("{}", line.join(" "));
+    lval_sexpr()
+}
+
+pub(crate) static VARIABLE_BUILTINS: &[Def] = &[
+    Def::builtin(
+        "\\",
+        &[arg("formals", LIST), arg("body", LIST)],
+        builtin_lambda,
+    ),
+    Def::builtin("def", &[arg("symbols", LIST)], builtin_def).rest(arg("values", ANY)),
+    Def::builtin("=", &[arg("symbols", LIST)], builtin_put).rest(arg("values", ANY)),
+    Def::builtin(
+        "fun",
+        &[arg("header", LIST), arg("body", LIST)],
+        builtin_fun,
+    ),
+];
+
+pub(crate) static LIST_BUILTINS: &[Def] = &[
+    Def::builtin("list", &[], builtin_list).rest(arg("xs", ANY)),
+    Def::builtin("head", &[arg("list", LIST)], builtin_head),
+    Def::builtin("tail", &[arg("list", LIST)], builtin_tail),
+    Def::special("eval", &[arg("expr", ANY)], special_eval),
+    Def::builtin("join", &[arg("list", LIST)], builtin_join).rest(arg("lists", LIST)),
+];
+
+pub(crate) static IO_BUILTINS: &[Def] = &[
+    Def::builtin("load", &[arg("path", STR)], builtin_load),
+    Def::builtin("print", &[], builtin_print).rest(arg("xs", ANY)),
+];
+
+/* Evaluate an S-expression as far as its tail position */
+fn lval_eval_sexpr(e: &Env, v: LispValue) -> Step {
+    let LispValue::Sexpr(cells, span) = v else {
+        return Step::Value(v);
+    };
+
+    /* Empty Expression */
+    if cells.is_empty() {
+        return Step::Value(LispValue::Sexpr(cells, span));
+    }
+    let mut cells = Rc::unwrap_or_clone(cells);
+
+    /* Remember where each child was read from, to locate errors */
+    let spans: Vec<Option<Span>> = cells.iter().map(|cell| cell.span().cloned()).collect();
+
+    /* Evaluate the first element, and pass a special form the rest as is */
+    let f = match cells.remove(0) {
+        LispValue::Sym(sym, head) if !cells.is_empty() => lval_function(e, sym, head),
+        head => lval_eval(e, head),
+    };
+    if let LispValue::Special(form) = &f {
+        let a = LispValue::Sexpr(Rc::new(cells), span.clone());
+        return match (form.func)(e, a) {
+            Step::Value(x) => Step::Value(lval_locate(x, &spans, span)),
+            tail => tail,
+        };
+    }
+
+    /* Likewise expand a macro, then evaluate its expansion in its place */
+    if let LispValue::Macro(m) = f {
+        return match lval_expand(m, LispValue::Sexpr(Rc::new(cells), span.clone())) {
+            err @ LispValue::Err(_) => Step::Value(lval_locate(err, &spans, span)),
+            x => lval_tail(e, x),
+        };
+    }
+
+    /* Evaluate Children, in a plain loop: each level of nesting passes
+    through here, and iterator adapters would add frames to it */
+    let mut evaluated = Vec::with_capacity(cells.len() + 1);
+    evaluated.push(f);
+    for cell in cells {
+        evaluated.push(lval_eval(e, cell));
+    }
+    let mut v = LispValue::Sexpr(Rc::new(evaluated), span.clone());
+
+    /* Error Checking */
+    if let Some(i) = v.cells().iter().position(|cell| cell.tag() == Tag::Err) {
+        return Step::Value(lval_take(v, i));
+    }
+    /* Single Expression */
+    if v.count() == 1 {
+        return Step::Value(lval_take(v, 0));
+    }
+
+    /* Call the first element, which must be a function after evaluation */
+    let f = lval_pop(&mut v, 0);
+    match lval_call(e, f, v) {
+        Step::Value(x) => Step::Value(lval_locate(x, &spans, span)),
+        tail => tail,
+    }
+}
+
+/// Look up `sym`, read at `span` as the first element of a call with
+/// arguments, which is an unknown function if it is unbound.
+pub(crate) fn lval_function(e: &Env, sym: Symbol, span: Option<Span>) -> LispValue {
+    e.lookup(sym)
+        .unwrap_or_else(|| lval_err(format!("Unknown function '{}'", sym)).with_span(span))
+}
+
+/// Give an error raised by a call the span of the argument it names (the
+/// function being argument 0), or else of the whole call.
+pub(crate) fn lval_locate(
+    result: LispValue,
+    spans: &[Option<Span>],
+    span: Option<Span>,
+) -> LispValue {
+    match result {
+        LispValue::Err(mut err) if err.span.is_none() => {
+            err.span = err
+                .arg
+                .and_then(|arg| spans.get(arg).cloned().flatten())
+                .or(span);
+            LispValue::Err(err)
+        }
+        other => other,
+    }
+}
+
+/// Apply a builtin or lambda to the arguments in the S-expression a. A
+/// lambda's body is left to evaluate in tail position.
+pub(crate) fn lval_call(e: &Env, f: LispValue, a: LispValue) -> Step {
+    match f {
+        LispValue::Fun(f) => Step::Value((f.func)(e, a)),
+        LispValue::Lambda(f) => lval_call_lambda(f, a),
+        /* Special forms only make sense applied to unevaluated code */
+        LispValue::Special(f) => Step::Value(lval_err_arg(
+            0,
+            format!("Special form '{}' cannot be applied to values.", f.name),
+        )),
+        LispValue::Macro(_) => Step::Value(lval_err_arg(0, "A macro cannot be applied to values.")),
+        other => Step::Value(lval_err_arg(
+            0,
+            format!(
+                "S-Expression starts with incorrect type. Got {}, Expected {}.",
+                other.tag(),
+                Tag::Fun
+            ),
+        )),
+    }
+}
+
+/// Apply the function `f` to the evaluated arguments in the S-expression
+/// `a`, as builtins like `map` do.
+pub(crate) fn lval_apply(e: &Env, f: LispValue, a: LispValue) -> LispValue {
+    let x = match f {
+        LispValue::Lambda(f) => lval_run_lambda(f, a),
+        f => match lval_call(e, f, a) {
+            Step::Value(x) => x,
+            Step::Eval(scope, body) => lval_eval(&scope, body),
+        },
+    };
+    match x {
+        /* The argument an error names is one of f's, not of the caller's */
+        LispValue::Err(mut err) => {
+            err.arg = None;
+            LispValue::Err(err)
+        }
+        x => x,
+    }
+}
+
+/// Apply the lambda `f` to the arguments in the S-expression `a`, and
+/// evaluate its body to the end with the evaluator of its environment.
+pub(crate) fn lval_run_lambda(f: Lambda, a: LispValue) -> LispValue {
+    if f.env.evaluator() == Evaluator::Bytecode {
+        return vm::vm_call(f, a);
+    }
+    match lval_call_lambda(f, a) {
+        Step::Value(x) => x,
+        Step::Eval(scope, body) => lval_eval(&scope, body),
+    }
+}
+
+/// Bind the arguments in the S-expression `a` to the formals of `f`, and
+/// leave its body to evaluate in tail position, or return `f` partially
+/// applied if there are too few.
+pub(crate) fn lval_call_lambda(f: Lambda, a: LispValue) -> Step {
+    match lval_bind(&f, a) {
+        Ok(scope) => lval_tail(&scope, Rc::unwrap_or_clone(f.body)),
+        Err(x) => Step::Value(x),
+    }
+}
+
+/// Bind the arguments in the S-expression `a` to the formals of `f` in a
+/// fresh scope to evaluate its body in. Fails with `f` partially applied if
+/// there are too few, or with an error if there are too many.
+pub(crate) fn lval_bind(f: &Lambda, mut a: LispValue) -> Result<Env, LispValue> {
+    let given = a.count();
+    let total = f.formals.len();
+    let mut formals = f.formals.iter().copied().peekable();
+
+    /* Arguments are bound in a fresh scope on top of the captured one */
+    let scope = Env::with_parent(&f.env);
+
+    while a.count() > 0 {
+        /* If we've ran out of formal arguments to bind */
+        let Some(sym) = formals.next() else {
+            return Err(lval_err(format!(
+                "Function passed too many arguments. Got {}, Expected {}.",
+                given, total
+            )));
+        };
+
+        /* Bind all remaining arguments to the symbol after '&' */
+        if sym == AMPERSAND {
+            let rest = formals.next().expect("checked when the lambda was built");
+            scope.put(rest, builtin_list(&scope, a));
+            break;
+        }
+
+        scope.put(sym, lval_pop(&mut a, 0));
+    }
+
+    /* If '&' remains in formal list bind to empty list */
+    if formals.next_if(|sym| *sym == AMPERSAND).is_some() {
+        let rest = formals.next().expect("checked when the lambda was built");
+        scope.put(rest, lval_qexpr());
+    }
+
+    let formals: Vec<Symbol> = formals.collect();
+    if formals.is_empty() {
+        /* All formals bound: the body is evaluated in the scope */
+        Ok(scope)
+    } else {
+        /* Otherwise return the partially applied function, sharing the body */
+        Err(LispValue::Lambda(Lambda {
+            env: scope,
+            formals: formals.into(),
+            body: f.body.clone(),
+            code: f.code.clone(),
+        }))
+    }
+}
+
+/// Evaluate the body `x` in `e` in tail position: a Q-expression as the
+/// S-expression it holds, as for lambda bodies and branches.
+pub(crate) fn lval_tail(e: &Env, x: LispValue) -> Step {
+    match x {
+        LispValue::Qexpr(cells, span) => Step::Eval(e.clone(), LispValue::Sexpr(cells, span)),
+        x => Step::Eval(e.clone(), x),
+    }
+}
+
+/// Evaluate `v` in the environment `e`, with the evaluator `e` is set to.
+///
+/// Tail positions (the bodies of lambdas, `eval` and the special forms)
+/// are evaluated in a loop here rather than by recursion, so they run in
+/// constant native stack. Other nesting counts towards the maximum depth of
+/// `e`, beyond which evaluation fails instead of overflowing the stack.
+pub(crate) fn lval_eval(e: &Env, v: LispValue) -> LispValue {
+    let Some(_guard) = e.enter() else {
+        return lval_err(format!(
+            "Recursion depth exceeded. Evaluation may nest at most {} deep.",
+            e.max_depth()
+        ))
+        .with_span(v.span().cloned());
+    };
+    if e.evaluator() == Evaluator::Bytecode {
+        return vm::vm_eval(e, v);
+    }
+
+    let mut e = e.clone();
+    let mut v = v;
+    loop {
+        let step = match v {
+            /* Look symbols up in the environment */
+            LispValue::Sym(sym, span) => match e.get(sym) {
+                err @ LispValue::Err(_) => return err.with_span(span),
+                x => return x,
+            },
+            /* Evaluate S-expressions */
+            LispValue::Sexpr(..) => lval_eval_sexpr(&e, v),
+            /* Treat all other types the same */
+            _ => return v,
+        };
+        match step {
+            Step::Value(x) => return x,
+            Step::Eval(tail_env, tail) => {
+                e = tail_env;
+                v = tail;
+            }
+        }
+    }
+}
@@ -0,0 +1,281 @@
+//! Special forms: builtins that receive their arguments unevaluated, and
+//! decide themselves what to evaluate and when.
+//!
+//! Branches and bodies may be written as Q-expressions, like the bodies of
+//! lambdas, and are then evaluated as the S-expression they hold:
+//! `if (> x 0) {print x} {print (- x)}`. Conditions must be booleans.
+//!
+//! Branches, and the last of a sequence of bodies, are in tail position:
+//! the forms hand them back to the evaluator as a [`Step::Eval`].
+
+use crate::{
+    env::Env,
+    eval::{lval_eval, lval_tail},
+    registry::{arg, Def, ANY, LIST},
+    value::{
+        lval_err, lval_err_arg, lval_err_type, lval_pop, lval_sexpr, lval_take, LispValue, Step,
+        Tag,
+    },
+};
+use std::rc::Rc;
+
+/* Evaluate a body that is not in tail position, a Q-expression as the
+S-expression it holds */
+fn lval_eval_body(e: &Env, x: LispValue) -> LispValue {
+    match x {
+        LispValue::Qexpr(cells, span) => lval_eval(e, LispValue::Sexpr(cells, span)),
+        x => lval_eval(e, x),
+    }
+}
+
+/* Evaluate bodies in order, leaving the last one in tail position. Stops
+at the first error, and gives () if there are no bodies */
+fn lval_eval_bodies(e: &Env, mut bodies: Vec<LispValue>) -> Step {
+    let Some(last) = bodies.pop() else {
+        return Step::Value(lval_sexpr());
+    };
+    for body in bodies {
+        let x = lval_eval_body(e, body);
+        if x.tag() == Tag::Err {
+            return Step::Value(x);
+        }
+    }
+    lval_tail(e, last)
+}
+
+/* Evaluate the condition passed to `func` as argument `arg` */
+fn lval_eval_test(e: &Env, func: &str, test: LispValue, arg: usize) -> Result<bool, LispValue> {
+    let span = test.span().cloned();
+    match lval_eval(e, test) {
+        LispValue::Bool(b, _) => Ok(b),
+        err @ LispValue::Err(_) => Err(err),
+        other => Err(lval_err_type(func, arg, other.tag(), Tag::Bool).with_span(span)),
+    }
+}
+
+/* The unevaluated arguments in a */
+fn lval_forms(a: LispValue) -> Vec<LispValue> {
+    match a {
+        LispValue::Sexpr(cells, _) => Rc::unwrap_or_clone(cells),
+        _ => Vec::new(),
+    }
+}
+
+/* if test then [else]: then if test is true, else else or () */
+fn special_if(e: &Env, mut a: LispValue) -> Step {
+    let test = lval_pop(&mut a, 0);
+    match lval_eval_test(e, "if", test, 1) {
+        Ok(true) => lval_tail(e, lval_pop(&mut a, 0)),
+        Ok(false) if a.count() == 2 => lval_tail(e, lval_pop(&mut a, 1)),
+        Ok(false) => Step::Value(lval_sexpr()),
+        Err(err) => Step::Value(err),
+    }
+}
+
+/* cond {test body...}...: the bodies of the first clause whose test is
+true, or () if there is none */
+fn special_cond(e: &Env, a: LispValue) -> Step {
+    for (i, clause) in lval_forms(a).into_iter().enumerate() {
+        let mut forms = match clause {
+            LispValue::Qexpr(forms, _) if !forms.is_empty() => Rc::unwrap_or_clone(forms),
+            LispValue::Qexpr(..) => {
+                return Step::Value(lval_err_arg(
+                    i + 1,
+                    format!("Function 'cond' passed {{}} for argument {}.", i + 1),
+                ))
+            }
+            _ => unreachable!("checked by the registry"),
+        };
+        let test = forms.remove(0);
+        match lval_eval_test(e, "cond", test, i + 1) {
+            Ok(true) => return lval_eval_bodies(e, forms),
+            Ok(false) => {}
+            Err(err) => return Step::Value(err),
+        }
+    }
+    Step::Value(lval_sexpr())
+}
+
+/* when test body...: the bodies if test is, or unless it is, true */
+fn special_when_unless(e: &Env, a: LispValue, func: &str, when: bool) -> Step {
+    let mut forms = lval_forms(a);
+    let test = forms.remove(0);
+    match lval_eval_test(e, func, test, 1) {
+        Ok(b) if b == when => lval_eval_bodies(e, forms),
+        Ok(_) => Step::Value(lval_sexpr()),
+        Err(err) => Step::Value(err),
+    }
+}
+
+fn special_when(e: &Env, a: LispValue) -> Step {
+    special_when_unless(e, a, "when", true)
+}
+
+fn special_unless(e: &Env, a: LispValue) -> Step {
+    special_when_unless(e, a, "unless", false)
+}
+
+/* let {sym value...} body...: the bodies evaluated in a new scope binding
+each symbol to its value in turn, so later values can use earlier symbols */
+fn special_let(e: &Env, a: LispValue) -> Step {
+    let mut forms = lval_forms(a);
+    let bindings = match forms.remove(0) {
+        LispValue::Qexpr(bindings, _) => Rc::unwrap_or_clone(bindings),
+        _ => unreachable!("checked by the registry"),
+    };
+    if bindings.len() % 2 != 0 {
+        return Step::Value(lval_err_arg(
+            1,
+            "Function 'let' passed a binding list with a symbol but no value.",
+        ));
+    }
+
+    let scope = Env::with_parent(e);
+    let mut bindings = bindings.into_iter();
+    while let (Some(sym), Some(value)) = (bindings.next(), bindings.next()) {
+        let LispValue::Sym(name, _) = sym else {
+            return Step::Value(
+                lval_err(format!(
+                    "Function 'let' cannot define non-symbol. Got {}, Expected {}.",
+                    sym.tag(),
+                    Tag::Sym
+                ))
+                .with_span(sym.span().cloned()),
+            );
+        };
+        match lval_eval(&scope, value) {
+            err @ LispValue::Err(_) => return Step::Value(err),
+            value => scope.put(name, value),
+        }
+    }
+    lval_eval_bodies(&scope, forms)
+}
+
+/* do body...: evaluate the bodies in order for the value of the last */
+fn special_do(e: &Env, a: LispValue) -> Step {
+    lval_eval_bodies(e, lval_forms(a))
+}
+
+/* The name of the quoting form x is, like (unquote y), and its argument */
+fn lval_quote_form(x: &LispValue) -> Option<(&str, &LispValue)> {
+    match x {
+        LispValue::Sexpr(cells, _) if cells.len() == 2 => match &cells[0] {
+            LispValue::Sym(sym, _)
+                if matches!(sym.as_str(), "quasiquote" | "unquote" | "unquote-splicing") =>
+            {
+                Some((sym, &cells[1]))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/* Fill in the template x. Only unquotes at level 0 are evaluated: each
+quasiquote nested in the template goes one level deeper, and each unquote
+one level back out */
+fn lval_quasi(e: &Env, x: LispValue, level: usize) -> Result<LispValue, LispValue> {
+    let (form, level) = match lval_quote_form(&x) {
+        Some(("unquote", _)) if level == 0 => {
+            return match lval_eval(e, lval_take(x, 1)) {
+                err @ LispValue::Err(_) => Err(err),
+                value => Ok(value),
+            }
+        }
+        Some(("unquote-splicing", _)) if level == 0 => {
+            return Err(lval_err("Function 'unquote-splicing' used outside a list.")
+                .with_span(x.span().cloned()))
+        }
+        Some(("quasiquote", _)) => (true, level + 1),
+        Some(_) => (true, level - 1),
+        None => (false, level),
+    };
+
+    match x {
+        /* Keep a nested quoting form, filling in its argument */
+        LispValue::Sexpr(mut cells, span) if form => {
+            let quoting = Rc::make_mut(&mut cells);
+            let arg = quoting.pop().expect("a quoting form has an argument");
+            quoting.push(lval_quasi(e, arg, level)?);
+            Ok(LispValue::Sexpr(cells, span))
+        }
+        LispValue::Sexpr(cells, span) => Ok(LispValue::Sexpr(
+            Rc::new(lval_quasi_list(e, Rc::unwrap_or_clone(cells), level)?),
+            span,
+        )),
+        LispValue::Qexpr(cells, span) => Ok(LispValue::Qexpr(
+            Rc::new(lval_quasi_list(e, Rc::unwrap_or_clone(cells), level)?),
+            span,
+        )),
+        other => Ok(other),
+    }
+}
+
+/* Fill in the elements of a list template, splicing in the elements of
+each Q-expression unquoted with ,@ */
+fn lval_quasi_list(
+    e: &Env,
+    cells: Vec<LispValue>,
+    level: usize,
+) -> Result<Vec<LispValue>, LispValue> {
+    let mut filled = Vec::with_capacity(cells.len());
+    for cell in cells {
+        if level > 0 || !matches!(lval_quote_form(&cell), Some(("unquote-splicing", _))) {
+            filled.push(lval_quasi(e, cell, level)?);
+            continue;
+        }
+        let x = lval_take(cell, 1);
+        let span = x.span().cloned();
+        match lval_eval(e, x) {
+            LispValue::Qexpr(cells, _) => filled.extend(Rc::unwrap_or_clone(cells)),
+            err @ LispValue::Err(_) => return Err(err),
+            other => {
+                return Err(
+                    lval_err_type("unquote-splicing", 1, other.tag(), Tag::Qexpr).with_span(span),
+                )
+            }
+        }
+    }
+    Ok(filled)
+}
+
+/* quasiquote template, or `template: the template as data, but with each
+,expr replaced by its value and each ,@expr by the elements of its value.
+An S-expression template gives a Q-expression, so `(+ 1 ,x) is {+ 1 5} if
+x is 5 */
+fn special_quasiquote(e: &Env, a: LispValue) -> Step {
+    let template = lval_take(a, 0);
+    let quoted = lval_quote_form(&template).is_some();
+    match lval_quasi(e, template, 0) {
+        Ok(LispValue::Sexpr(cells, span)) if !quoted => Step::Value(LispValue::Qexpr(cells, span)),
+        Ok(x) | Err(x) => Step::Value(x),
+    }
+}
+
+/* unquote and unquote-splicing only mean something in a quasiquote */
+fn special_unquote(_e: &Env, _a: LispValue) -> Step {
+    Step::Value(lval_err("Function 'unquote' used outside quasiquote."))
+}
+
+fn special_unquote_splicing(_e: &Env, _a: LispValue) -> Step {
+    Step::Value(lval_err(
+        "Function 'unquote-splicing' used outside quasiquote.",
+    ))
+}
+
+pub(crate) static SPECIAL_FORMS: &[Def] = &[
+    Def::special("if", &[arg("test", ANY), arg("then", ANY)], special_if)
+        .optional(&[arg("else", ANY)]),
+    Def::special("cond", &[], special_cond).rest(arg("clauses", LIST)),
+    Def::special("when", &[arg("test", ANY)], special_when).rest(arg("body", ANY)),
+    Def::special("unless", &[arg("test", ANY)], special_unless).rest(arg("body", ANY)),
+    Def::special("let", &[arg("bindings", LIST)], special_let).rest(arg("body", ANY)),
+    Def::special("do", &[], special_do).rest(arg("body", ANY)),
+    Def::special("quasiquote", &[arg("template", ANY)], special_quasiquote),
+    Def::special("unquote", &[arg("x", ANY)], special_unquote),
+    Def::special(
+        "unquote-splicing",
+        &[arg("x", ANY)],
+        special_unquote_splicing,
+    ),
+];
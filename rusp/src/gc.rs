@@ -0,0 +1,265 @@
+//! A tracing collector for the cycles reference counting cannot free.
+//!
+//! Values are trees, and lists, maps, sets and lambda bodies are shared by
+//! reference counting, so none of them can make a cycle on their own. But a
+//! lambda holds the scope it closes over, and that scope may bind the
+//! lambda, directly or through other scopes and values:
+//!
+//! ```text
+//! fun {counter n} {let {m n} (= {next} (\ {} {+ m 1}))}
+//! ```
+//!
+//! Each call of `counter` leaves a scope that is only kept alive by the
+//! lambda it binds. The heap tracks every scope, and now and then collects
+//! those kept alive only by such cycles.
+//!
+//! Where the roots are is not known up front: a scope may be held by the
+//! interpreter, by a value on the native stack in the middle of an
+//! evaluation, or by a host's closure. So, like CPython's cycle collector,
+//! a collection first counts the references each scope, and each shared
+//! value it reaches, receives from within the heap. Anything with more
+//! references than that is held from outside, and is a root. Everything
+//! reachable from the roots is marked, and the remaining scopes are swept:
+//! their bindings are cleared, which breaks the cycles so that reference
+//! counting frees them.
+
+use crate::{
+    collections::Key,
+    env::{Env, Scope},
+    value::LispValue,
+};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap},
+    mem,
+    rc::{Rc, Weak},
+};
+
+/* The fewest scopes to create between collections */
+const MIN_INTERVAL: usize = 1024;
+
+/// Every scope of a global environment and those created on top of it.
+pub(crate) struct Heap {
+    scopes: RefCell<Vec<Weak<RefCell<Scope>>>>,
+    /* Scopes created since the last collection, and how many to wait for
+    before the next */
+    created: Cell<usize>,
+    interval: Cell<usize>,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self {
+            scopes: RefCell::default(),
+            created: Cell::new(0),
+            interval: Cell::new(MIN_INTERVAL),
+        }
+    }
+}
+
+impl Heap {
+    /// Track the new scope `env`, collecting if enough have been created
+    /// since the last collection.
+    pub(crate) fn track(&self, env: &Env) {
+        self.scopes.borrow_mut().push(Rc::downgrade(&env.0));
+        self.created.set(self.created.get() + 1);
+        if self.created.get() >= self.interval.get() {
+            collect(self);
+        }
+    }
+}
+
+/* Something in the heap that may be shared: a scope, or a value shared by
+reference counting that may lead to one */
+enum Node {
+    Scope(Rc<RefCell<Scope>>),
+    List(Rc<Vec<LispValue>>),
+    Map(Rc<BTreeMap<Key, LispValue>>),
+    Body(Rc<LispValue>),
+}
+
+impl Node {
+    /* Identifies the node, while the graph holds it */
+    fn addr(&self) -> usize {
+        match self {
+            Node::Scope(x) => Rc::as_ptr(x) as *const u8 as usize,
+            Node::List(x) => Rc::as_ptr(x) as *const u8 as usize,
+            Node::Map(x) => Rc::as_ptr(x) as *const u8 as usize,
+            Node::Body(x) => Rc::as_ptr(x) as *const u8 as usize,
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Node::Scope(x) => Rc::strong_count(x),
+            Node::List(x) => Rc::strong_count(x),
+            Node::Map(x) => Rc::strong_count(x),
+            Node::Body(x) => Rc::strong_count(x),
+        }
+    }
+
+    /* Add the nodes this one refers to directly to `out`, counting the
+    values visited in `work`. False if it is borrowed for changing, so that
+    it cannot be traced */
+    fn trace(&self, out: &mut Vec<Node>, work: &mut usize) -> bool {
+        match self {
+            Node::Scope(scope) => {
+                let Ok(scope) = scope.try_borrow() else {
+                    return false;
+                };
+                if let Some(parent) = &scope.parent {
+                    out.push(Node::Scope(parent.0.clone()));
+                }
+                for x in scope.vars.values() {
+                    trace_value(x, out, work);
+                }
+            }
+            Node::List(cells) => {
+                for x in cells.iter() {
+                    trace_value(x, out, work);
+                }
+            }
+            Node::Map(map) => {
+                for x in map.values() {
+                    trace_value(x, out, work);
+                }
+            }
+            Node::Body(body) => trace_value(body, out, work),
+        }
+        true
+    }
+}
+
+/* Add the nodes the value x refers to to `out` */
+fn trace_value(x: &LispValue, out: &mut Vec<Node>, work: &mut usize) {
+    let mut stack = vec![x];
+    while let Some(x) = stack.pop() {
+        *work += 1;
+        match x {
+            LispValue::Lambda(f) | LispValue::Macro(f) => {
+                out.push(Node::Scope(f.env.0.clone()));
+                out.push(Node::Body(f.body.clone()));
+            }
+            /* A list only x refers to is part of x, but one that is
+            shared may be held from outside the heap too */
+            LispValue::Sexpr(cells, _) | LispValue::Qexpr(cells, _) => {
+                if Rc::strong_count(cells) == 1 {
+                    stack.extend(cells.iter());
+                } else {
+                    out.push(Node::List(cells.clone()));
+                }
+            }
+            LispValue::Map(map) => out.push(Node::Map(map.clone())),
+            /* Sets hold only keys, and builtins cannot be traced: anything
+            their closures hold counts as held from outside */
+            _ => {}
+        }
+    }
+}
+
+/* The nodes reachable from the tracked scopes, and the references between
+them. The graph holds one reference to each node */
+struct Graph {
+    nodes: Vec<Node>,
+    edges: Vec<Vec<usize>>,
+    /* The references each node receives from other nodes */
+    internal: Vec<usize>,
+    traced: Vec<bool>,
+    /* The values visited tracing each node */
+    work: Vec<usize>,
+}
+
+impl Graph {
+    fn build(scopes: Vec<Rc<RefCell<Scope>>>) -> Graph {
+        let mut graph = Graph {
+            nodes: scopes.into_iter().map(Node::Scope).collect(),
+            edges: Vec::new(),
+            internal: Vec::new(),
+            traced: Vec::new(),
+            work: Vec::new(),
+        };
+        graph.internal.resize(graph.nodes.len(), 0);
+        let mut index: HashMap<usize, usize> = graph
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.addr(), i))
+            .collect();
+
+        /* Nodes found along the way are appended, and traced in turn */
+        let mut i = 0;
+        while i < graph.nodes.len() {
+            let mut children = Vec::new();
+            let mut work = 0;
+            let traced = graph.nodes[i].trace(&mut children, &mut work);
+            let mut edges = Vec::with_capacity(children.len());
+            for child in children {
+                let j = *index.entry(child.addr()).or_insert_with(|| {
+                    graph.nodes.push(child);
+                    graph.internal.push(0);
+                    graph.nodes.len() - 1
+                });
+                graph.internal[j] += 1;
+                edges.push(j);
+            }
+            graph.edges.push(edges);
+            graph.traced.push(traced);
+            graph.work.push(work);
+            i += 1;
+        }
+        graph
+    }
+
+    /* Which nodes are reachable from the roots: those referenced from
+    outside the heap, beyond the graph's own reference, or not traced */
+    fn mark(&self) -> Vec<bool> {
+        let mut marked = vec![false; self.nodes.len()];
+        let mut stack: Vec<usize> = (0..self.nodes.len())
+            .filter(|&i| !self.traced[i] || self.nodes[i].strong_count() > self.internal[i] + 1)
+            .collect();
+        while let Some(i) = stack.pop() {
+            if !marked[i] {
+                marked[i] = true;
+                stack.extend(&self.edges[i]);
+            }
+        }
+        marked
+    }
+}
+
+/// Reclaim the scopes in `heap` kept alive only by cycles, returning how
+/// many there were.
+pub(crate) fn collect(heap: &Heap) -> usize {
+    let scopes: Vec<Rc<RefCell<Scope>>> = {
+        let mut tracked = heap.scopes.borrow_mut();
+        tracked.retain(|scope| scope.strong_count() > 0);
+        tracked.iter().filter_map(Weak::upgrade).collect()
+    };
+    let graph = Graph::build(scopes);
+    let marked = graph.mark();
+
+    /* Clear the unmarked scopes, dropping what they held only once none is
+    borrowed */
+    let mut garbage = Vec::new();
+    let mut live_work = 0;
+    for (i, node) in graph.nodes.iter().enumerate() {
+        match node {
+            _ if marked[i] => live_work += graph.work[i],
+            Node::Scope(scope) => {
+                if let Ok(mut scope) = scope.try_borrow_mut() {
+                    garbage.push((mem::take(&mut scope.vars), scope.parent.take()));
+                }
+            }
+            _ => {}
+        }
+    }
+    let reclaimed = garbage.len();
+
+    /* Wait for as many new scopes as there were live values to trace, so
+    collecting costs a constant amount per scope created */
+    heap.created.set(0);
+    heap.interval.set(live_work.max(MIN_INTERVAL));
+    drop(graph);
+    drop(garbage);
+    reclaimed
+}
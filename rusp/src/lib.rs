@@ -0,0 +1,235 @@
+//! rusp, the lispy language from "Build Your Own Lisp", as an embeddable
+//! interpreter.
+//!
+//! ```
+//! use rusp::{lval_num, Interpreter};
+//!
+//! let mut interpreter = Interpreter::new();
+//! assert_eq!(interpreter.eval_str("+ 1 (* 2 3)").unwrap(), lval_num(7));
+//! ```
+
+mod collections;
+mod compile;
+mod env;
+mod eval;
+mod forms;
+mod gc;
+mod lists;
+mod logic;
+mod macros;
+mod math;
+mod number;
+mod reader;
+mod registry;
+mod span;
+mod strings;
+mod symbol;
+mod value;
+mod vm;
+
+pub use collections::Key;
+pub use env::{Env, DEFAULT_MAX_DEPTH, STACK_PER_DEPTH};
+pub use number::{Division, Number};
+pub use reader::{lval_read, ParseError};
+pub use span::{Source, Span};
+pub use symbol::Symbol;
+pub use value::{
+    lval_add, lval_bool, lval_err, lval_err_arg, lval_err_count, lval_err_type, lval_float,
+    lval_fun, lval_join, lval_lambda, lval_macro, lval_num, lval_pop, lval_qexpr, lval_sexpr,
+    lval_special, lval_str, lval_sym, lval_take, Builtin, BuiltinFn, Lambda, LispError, LispValue,
+    Special, SpecialFn, Step, Tag,
+};
+pub use vm::Evaluator;
+
+use eval::{lval_eval, lval_eval_all, lval_load};
+use math::lenv_add_division;
+use registry::lenv_add_registry;
+use std::fmt;
+
+/// The value type produced by [`Interpreter::eval_str`].
+pub type Value = LispValue;
+
+/// Why [`Interpreter::eval_str`] could not produce a value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The input is not well-formed.
+    Parse(ParseError),
+    /// Evaluation produced an error value.
+    Eval(LispError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse(err) => write!(f, "{}", err),
+            Error::Eval(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Self {
+        Error::Parse(err)
+    }
+}
+
+/// A lispy interpreter and its global environment.
+pub struct Interpreter {
+    env: Env,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    /// Create an interpreter with the builtins bound in its global environment.
+    pub fn new() -> Self {
+        let env = Env::new();
+        lenv_add_registry(&env);
+        Self { env }
+    }
+
+    /// The global environment, where `def` binds names.
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+
+    /// Make `func` callable from lispy code as `name`.
+    ///
+    /// Like the builtins, `func` receives its evaluated arguments as an
+    /// S-expression and reports failure by returning [`lval_err`].
+    /// Registering an existing name, builtin or not, replaces its binding.
+    ///
+    /// ```
+    /// use rusp::{lval_num, Interpreter, Value};
+    ///
+    /// let mut interpreter = Interpreter::new();
+    /// interpreter.register("count", |args: Value| lval_num(args.count() as i64));
+    /// assert_eq!(interpreter.eval_str("count 1 2 3").unwrap(), lval_num(3));
+    /// ```
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        func: impl Fn(LispValue) -> LispValue + 'static,
+    ) {
+        let name = name.into();
+        self.env
+            .put(name.as_str(), lval_fun(name.as_str(), move |_, a| func(a)));
+    }
+
+    /// Choose how `/` divides two integers: exactly, the default, or
+    /// truncating as the C implementation does. This rebinds `/` in the
+    /// global environment.
+    ///
+    /// ```
+    /// use rusp::{lval_num, Division, Interpreter};
+    ///
+    /// let mut interpreter = Interpreter::new();
+    /// assert_eq!(interpreter.eval_str("/ 7 2").unwrap().to_string(), "7/2");
+    /// interpreter.set_division(Division::Truncate);
+    /// assert_eq!(interpreter.eval_str("/ 7 2").unwrap(), lval_num(3));
+    /// ```
+    pub fn set_division(&mut self, division: Division) {
+        lenv_add_division(&self.env, division);
+    }
+
+    /// Limit how deeply evaluations may nest before failing with a
+    /// "Recursion depth exceeded" error, rather than overflowing the native
+    /// stack. Calls in tail position do not nest.
+    ///
+    /// Allow [`STACK_PER_DEPTH`], 8 KiB, of native stack per level: the
+    /// default, [`DEFAULT_MAX_DEPTH`], fits an 8 MiB main thread, but a
+    /// thread spawned with the standard library's default 2 MiB stack needs
+    /// a depth of at most 256. To nest deeper, evaluate on a thread with a
+    /// larger stack, as the `rusp` binary does.
+    pub fn set_max_depth(&mut self, depth: usize) {
+        self.env.set_max_depth(depth);
+    }
+
+    /// Choose how expressions are evaluated: by walking them as read, the
+    /// default, or by compiling them to bytecode for a stack machine. Both
+    /// give the same values and errors.
+    ///
+    /// ```
+    /// use rusp::{lval_num, Evaluator, Interpreter};
+    ///
+    /// let mut interpreter = Interpreter::new();
+    /// interpreter.set_evaluator(Evaluator::Bytecode);
+    /// interpreter.eval_str("fun {fact n} {if (== n 0) {1} {* n (fact (- n 1))}}").unwrap();
+    /// assert_eq!(interpreter.eval_str("fact 5").unwrap(), lval_num(120));
+    /// ```
+    pub fn set_evaluator(&mut self, evaluator: Evaluator) {
+        self.env.set_evaluator(evaluator);
+    }
+
+    /// Reclaim the scopes kept alive only by cycles, returning how many
+    /// there were. See [`Env::collect_garbage`].
+    ///
+    /// ```
+    /// use rusp::Interpreter;
+    ///
+    /// let mut interpreter = Interpreter::new();
+    /// let script = "(fun {counter n} {let {m n} (= {next} (\\ {} {+ m 1}))}) (counter 1) (counter 2)";
+    /// interpreter.eval_source("counter.lspy", script).unwrap();
+    /// assert_eq!(interpreter.collect_garbage(), 4);
+    /// assert_eq!(interpreter.collect_garbage(), 0);
+    /// ```
+    pub fn collect_garbage(&mut self) -> usize {
+        self.env.collect_garbage()
+    }
+
+    /// Read `input` as one expression and evaluate it, as the prompt does.
+    /// Errors are located in a source named `<stdin>`.
+    pub fn eval_str(&mut self, input: &str) -> Result<Value, Error> {
+        self.eval_expr("<stdin>", input)
+    }
+
+    /// Read `input` as one expression, from a source named `name`, and
+    /// evaluate it, as [`eval_str`](Self::eval_str) does.
+    ///
+    /// ```
+    /// use rusp::Interpreter;
+    ///
+    /// let mut interpreter = Interpreter::new();
+    /// let err = interpreter.eval_expr("<expr>", "+ 1 {}").unwrap_err();
+    /// assert!(err.to_string().contains("--> <expr>:1:5"));
+    /// ```
+    pub fn eval_expr(&mut self, name: &str, input: &str) -> Result<Value, Error> {
+        let tree = lval_read(name, input)?;
+        lval_result(lval_eval(&self.env, tree))
+    }
+
+    /// Read `text` as a script named `name` and evaluate each top-level
+    /// expression in order, returning the value of the last one.
+    /// Evaluation stops at the first error.
+    ///
+    /// ```
+    /// use rusp::{lval_num, Interpreter};
+    ///
+    /// let mut interpreter = Interpreter::new();
+    /// let script = "(def {x} 20)\n(+ x 1)";
+    /// assert_eq!(interpreter.eval_source("script.lspy", script).unwrap(), lval_num(21));
+    /// ```
+    pub fn eval_source(&mut self, name: &str, text: &str) -> Result<Value, Error> {
+        let exprs = lval_read(name, text)?;
+        lval_result(lval_eval_all(&self.env, exprs))
+    }
+
+    /// Evaluate the script at `path` into the global environment, like the
+    /// `load` builtin. Use it to load a prelude before running user code.
+    pub fn load(&mut self, path: &str) -> Result<(), Error> {
+        lval_result(lval_load(&self.env, path)).map(drop)
+    }
+}
+
+fn lval_result(value: LispValue) -> Result<Value, Error> {
+    match value {
+        LispValue::Err(err) => Err(Error::Eval(*err)),
+        value => Ok(value),
+    }
+}
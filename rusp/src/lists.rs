@@ -0,0 +1,319 @@
+//! The list library: builtins operating on Q-expressions.
+//!
+//! Argument orders follow the prelude of "Build Your Own Lisp", with the
+//! list last: `nth 1 {a b c}` is `b`, `map f {1 2 3}` and `foldl f z l`.
+
+use crate::{
+    env::Env,
+    eval::lval_apply,
+    logic::lval_eq,
+    number::Number,
+    registry::{arg, Def, ANY, FUN, LIST, NUM},
+    strings::index_arg,
+    value::{
+        lval_add, lval_bool, lval_err, lval_err_arg, lval_num, lval_pop, lval_qexpr, lval_sexpr,
+        lval_take, LispValue, Tag,
+    },
+};
+use std::{cmp::Ordering, rc::Rc};
+
+/* Check that the Q-expression argument at position `arg` of `func` is not
+empty */
+fn lval_check_nonempty(func: &str, a: &LispValue, arg: usize) -> Result<(), LispValue> {
+    match a.cells()[arg - 1].count() {
+        0 => Err(lval_err_arg(
+            arg,
+            format!("Function '{}' passed {{}} for argument {}.", func, arg),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/* The elements of a Q-expression */
+fn lval_elements(x: LispValue) -> Vec<LispValue> {
+    match x {
+        LispValue::Qexpr(cells, _) => Rc::unwrap_or_clone(cells),
+        _ => Vec::new(),
+    }
+}
+
+/* A Q-expression holding cells */
+fn lval_list(cells: Vec<LispValue>) -> LispValue {
+    LispValue::Qexpr(Rc::new(cells), None)
+}
+
+/* cons x {xs...}: {x xs...} */
+fn builtin_cons(_e: &Env, mut a: LispValue) -> LispValue {
+    let x = lval_pop(&mut a, 0);
+    let mut v = lval_take(a, 0);
+    v.cells_mut().insert(0, x);
+    v
+}
+
+/* nth n {xs...}: the element at index n, counting from 0 */
+fn builtin_nth(_e: &Env, a: LispValue) -> LispValue {
+    let n = match index_arg("nth", &a, 1) {
+        Ok(n) => n,
+        Err(err) => return err,
+    };
+    let len = a.cells()[1].count();
+    if n >= len {
+        return lval_err_arg(
+            1,
+            format!(
+                "Function 'nth' passed index {} out of range for length {}.",
+                n, len
+            ),
+        );
+    }
+    lval_take(lval_take(a, 1), n)
+}
+
+/* last {xs... x}: x */
+fn builtin_last(_e: &Env, a: LispValue) -> LispValue {
+    if let Err(err) = lval_check_nonempty("last", &a, 1) {
+        return err;
+    }
+    let mut v = lval_take(a, 0);
+    v.cells_mut().pop().expect("checked not empty")
+}
+
+/* init {xs... x}: {xs...} */
+fn builtin_init(_e: &Env, a: LispValue) -> LispValue {
+    if let Err(err) = lval_check_nonempty("init", &a, 1) {
+        return err;
+    }
+    let mut v = lval_take(a, 0);
+    v.cells_mut().pop();
+    v
+}
+
+fn builtin_reverse(_e: &Env, a: LispValue) -> LispValue {
+    let mut v = lval_take(a, 0);
+    v.cells_mut().reverse();
+    v
+}
+
+/* take n {xs...}: the first n elements, or all if there are fewer. With
+drop, `join (take n l) (drop n l)` is l */
+fn builtin_take(_e: &Env, a: LispValue) -> LispValue {
+    let n = match index_arg("take", &a, 1) {
+        Ok(n) => n,
+        Err(err) => return err,
+    };
+    let mut v = lval_take(a, 1);
+    v.cells_mut().truncate(n);
+    v
+}
+
+/* drop n {xs...}: all but the first n elements */
+fn builtin_drop(_e: &Env, a: LispValue) -> LispValue {
+    let n = match index_arg("drop", &a, 1) {
+        Ok(n) => n,
+        Err(err) => return err,
+    };
+    let mut v = lval_take(a, 1);
+    let n = n.min(v.count());
+    v.cells_mut().drain(..n);
+    v
+}
+
+/* The function and list arguments, as in map f {xs...} */
+fn lval_fun_list(mut a: LispValue) -> (LispValue, Vec<LispValue>) {
+    let f = lval_pop(&mut a, 0);
+    (f, lval_elements(lval_take(a, 0)))
+}
+
+/* f applied to the single argument x */
+fn lval_apply_1(e: &Env, f: &LispValue, x: LispValue) -> LispValue {
+    lval_apply(e, f.clone(), lval_add(lval_sexpr(), x))
+}
+
+/* map f {xs...}: {(f x)...} */
+fn builtin_map(e: &Env, a: LispValue) -> LispValue {
+    let (f, xs) = lval_fun_list(a);
+    let mut mapped = Vec::with_capacity(xs.len());
+    for x in xs {
+        match lval_apply_1(e, &f, x) {
+            err @ LispValue::Err(_) => return err,
+            y => mapped.push(y),
+        }
+    }
+    lval_list(mapped)
+}
+
+/* filter f {xs...}: the elements x for which (f x) is true */
+fn builtin_filter(e: &Env, a: LispValue) -> LispValue {
+    let (f, xs) = lval_fun_list(a);
+    let mut kept = Vec::new();
+    for x in xs {
+        match lval_apply_1(e, &f, x.clone()) {
+            LispValue::Bool(true, _) => kept.push(x),
+            LispValue::Bool(false, _) => {}
+            err @ LispValue::Err(_) => return err,
+            other => {
+                return lval_err(format!(
+                    "Function 'filter' passed a function returning {}. Expected {}.",
+                    other.tag(),
+                    Tag::Bool
+                ))
+            }
+        }
+    }
+    lval_list(kept)
+}
+
+/* foldl f z {xs...} folds from the left, f (f (f z x1) x2) x3; foldr f z
+{xs...} from the right, f x1 (f x2 (f x3 z)) */
+fn builtin_fold(e: &Env, mut a: LispValue, left: bool) -> LispValue {
+    let f = lval_pop(&mut a, 0);
+    let mut acc = lval_pop(&mut a, 0);
+    let mut xs = lval_elements(lval_take(a, 0));
+    if !left {
+        xs.reverse();
+    }
+
+    for x in xs {
+        let args = if left { [acc, x] } else { [x, acc] };
+        acc = lval_apply(e, f.clone(), args.into_iter().fold(lval_sexpr(), lval_add));
+        if acc.tag() == Tag::Err {
+            break;
+        }
+    }
+    acc
+}
+
+fn builtin_foldl(e: &Env, a: LispValue) -> LispValue {
+    builtin_fold(e, a, true)
+}
+
+fn builtin_foldr(e: &Env, a: LispValue) -> LispValue {
+    builtin_fold(e, a, false)
+}
+
+/* The integer argument at position `arg` of `func` */
+fn int_arg(func: &str, a: &LispValue, arg: usize) -> Result<i64, LispValue> {
+    match &a.cells()[arg - 1] {
+        LispValue::Num(Number::Int(n), _) => Ok(*n),
+        LispValue::Num(n @ Number::Big(_), _) => Err(lval_err_arg(
+            arg,
+            format!("Function '{}' passed {} out of range.", func, n),
+        )),
+        LispValue::Num(n, _) => Err(lval_err_arg(
+            arg,
+            format!("Function '{}' passed non-integer {}.", func, n),
+        )),
+        _ => unreachable!("checked by the registry"),
+    }
+}
+
+/* The most elements range gives, so that a typo cannot exhaust memory */
+const RANGE_MAX_LEN: i128 = 1 << 24;
+
+/* range [start] end: {start start+1 ... end-1}, start being 0 if omitted */
+fn builtin_range(_e: &Env, a: LispValue) -> LispValue {
+    let bounds = match a.count() {
+        1 => (Ok(0), int_arg("range", &a, 1)),
+        _ => (int_arg("range", &a, 1), int_arg("range", &a, 2)),
+    };
+    let (start, end) = match bounds {
+        (Ok(start), Ok(end)) => (start, end),
+        (Err(err), _) | (_, Err(err)) => return err,
+    };
+    if i128::from(end) - i128::from(start) > RANGE_MAX_LEN {
+        return lval_err(format!(
+            "Function 'range' passed range {}..{} of more than {} elements.",
+            start, end, RANGE_MAX_LEN
+        ));
+    }
+    lval_list((start..end).map(lval_num).collect())
+}
+
+/* zip {xs...} {ys...}: {{x y}...}, as long as the shorter list */
+fn builtin_zip(_e: &Env, mut a: LispValue) -> LispValue {
+    let xs = lval_elements(lval_pop(&mut a, 0));
+    let ys = lval_elements(lval_take(a, 0));
+    lval_list(
+        xs.into_iter()
+            .zip(ys)
+            .map(|(x, y)| lval_add(lval_add(lval_qexpr(), x), y))
+            .collect(),
+    )
+}
+
+/* Order two elements of a list being sorted, which must be both numbers
+or both strings */
+fn lval_order(x: &LispValue, y: &LispValue) -> Option<Ordering> {
+    match (x, y) {
+        /* Order NaN after everything, as total_cmp does, so the order is total */
+        (LispValue::Num(x, _), LispValue::Num(y, _)) => x
+            .compare(y)
+            .or_else(|| Some(x.to_f64().total_cmp(&y.to_f64()))),
+        (LispValue::Str(x, _), LispValue::Str(y, _)) => Some(x.cmp(y)),
+        _ => None,
+    }
+}
+
+/* sort {xs...}: the numbers or strings in xs in ascending order. The sort
+is stable, so numbers that are equal in value keep their order */
+fn builtin_sort(_e: &Env, a: LispValue) -> LispValue {
+    let mut v = lval_take(a, 0);
+    let cells = v.cells_mut();
+    if let Some(first) = cells.first() {
+        if let Some(other) = cells.iter().find(|x| lval_order(first, x).is_none()) {
+            let message = if other.tag() == first.tag() {
+                format!("Function 'sort' cannot order {}s.", other.tag())
+            } else {
+                format!(
+                    "Function 'sort' passed a list mixing {}s and {}s.",
+                    first.tag(),
+                    other.tag()
+                )
+            };
+            return lval_err_arg(1, message);
+        }
+    }
+    cells.sort_by(|x, y| lval_order(x, y).expect("checked comparable"));
+    v
+}
+
+/* contains x {xs...}: whether some element equals x, as == sees it */
+fn builtin_contains(_e: &Env, a: LispValue) -> LispValue {
+    let x = &a.cells()[0];
+    lval_bool(a.cells()[1].cells().iter().any(|y| lval_eq(x, y)))
+}
+
+pub(crate) static LIST_LIBRARY: &[Def] = &[
+    Def::builtin("cons", &[arg("x", ANY), arg("list", LIST)], builtin_cons),
+    Def::builtin("nth", &[arg("n", NUM), arg("list", LIST)], builtin_nth),
+    Def::builtin("last", &[arg("list", LIST)], builtin_last),
+    Def::builtin("init", &[arg("list", LIST)], builtin_init),
+    Def::builtin("reverse", &[arg("list", LIST)], builtin_reverse),
+    Def::builtin("take", &[arg("n", NUM), arg("list", LIST)], builtin_take),
+    Def::builtin("drop", &[arg("n", NUM), arg("list", LIST)], builtin_drop),
+    Def::builtin("map", &[arg("f", FUN), arg("list", LIST)], builtin_map),
+    Def::builtin(
+        "filter",
+        &[arg("f", FUN), arg("list", LIST)],
+        builtin_filter,
+    ),
+    Def::builtin(
+        "foldl",
+        &[arg("f", FUN), arg("z", ANY), arg("list", LIST)],
+        builtin_foldl,
+    ),
+    Def::builtin(
+        "foldr",
+        &[arg("f", FUN), arg("z", ANY), arg("list", LIST)],
+        builtin_foldr,
+    ),
+    /* range n is range 0 n */
+    Def::builtin("range", &[arg("n", NUM)], builtin_range).optional(&[arg("end", NUM)]),
+    Def::builtin("zip", &[arg("xs", LIST), arg("ys", LIST)], builtin_zip),
+    Def::builtin("sort", &[arg("list", LIST)], builtin_sort),
+    Def::builtin(
+        "contains",
+        &[arg("x", ANY), arg("list", LIST)],
+        builtin_contains,
+    ),
+];
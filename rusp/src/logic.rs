@@ -0,0 +1,94 @@
+//! Comparison, equality and boolean builtins.
+
+use crate::{
+    env::Env,
+    registry::{arg, Def, ANY, BOOL, NUM},
+    value::{lval_bool, LispValue},
+};
+use std::cmp::Ordering;
+
+/// Whether `x` and `y` are equal as `==` sees them: numbers by value, so
+/// that `1` equals `1.0`, S- and Q-expressions by comparing their children
+/// in turn, and everything else as [`LispValue`]'s `PartialEq` does.
+pub(crate) fn lval_eq(x: &LispValue, y: &LispValue) -> bool {
+    match (x, y) {
+        (LispValue::Num(x, _), LispValue::Num(y, _)) => x.compare(y) == Some(Ordering::Equal),
+        (LispValue::Sexpr(x, _), LispValue::Sexpr(y, _))
+        | (LispValue::Qexpr(x, _), LispValue::Qexpr(y, _)) => {
+            x.len() == y.len() && x.iter().zip(y.iter()).all(|(x, y)| lval_eq(x, y))
+        }
+        (x, y) => x == y,
+    }
+}
+
+/* Whether the first number compares to the second as `holds` wants.
+Comparisons with NaN are all false */
+fn builtin_ord(a: LispValue, holds: fn(Ordering) -> bool) -> LispValue {
+    let (LispValue::Num(x, _), LispValue::Num(y, _)) = (&a.cells()[0], &a.cells()[1]) else {
+        unreachable!("checked by the registry");
+    };
+    lval_bool(x.compare(y).is_some_and(holds))
+}
+
+fn builtin_gt(_e: &Env, a: LispValue) -> LispValue {
+    builtin_ord(a, Ordering::is_gt)
+}
+
+fn builtin_lt(_e: &Env, a: LispValue) -> LispValue {
+    builtin_ord(a, Ordering::is_lt)
+}
+
+fn builtin_ge(_e: &Env, a: LispValue) -> LispValue {
+    builtin_ord(a, Ordering::is_ge)
+}
+
+fn builtin_le(_e: &Env, a: LispValue) -> LispValue {
+    builtin_ord(a, Ordering::is_le)
+}
+
+fn builtin_cmp(a: LispValue, equal: bool) -> LispValue {
+    lval_bool(lval_eq(&a.cells()[0], &a.cells()[1]) == equal)
+}
+
+fn builtin_eq(_e: &Env, a: LispValue) -> LispValue {
+    builtin_cmp(a, true)
+}
+
+fn builtin_ne(_e: &Env, a: LispValue) -> LispValue {
+    builtin_cmp(a, false)
+}
+
+/* The arguments as booleans, which the registry checked they are */
+fn lval_bools(a: &LispValue) -> impl Iterator<Item = bool> + '_ {
+    a.cells().iter().map(|cell| match cell {
+        LispValue::Bool(b, _) => *b,
+        _ => unreachable!("checked by the registry"),
+    })
+}
+
+fn builtin_and(_e: &Env, a: LispValue) -> LispValue {
+    /* True if every argument is, including when there are none */
+    lval_bool(lval_bools(&a).all(|b| b))
+}
+
+fn builtin_or(_e: &Env, a: LispValue) -> LispValue {
+    /* True if any argument is, so false when there are none */
+    lval_bool(lval_bools(&a).any(|b| b))
+}
+
+fn builtin_not(_e: &Env, a: LispValue) -> LispValue {
+    let b = lval_bools(&a).next().expect("checked by the registry");
+    lval_bool(!b)
+}
+
+pub(crate) static LOGIC_BUILTINS: &[Def] = &[
+    Def::builtin(">", &[arg("x", NUM), arg("y", NUM)], builtin_gt),
+    Def::builtin("<", &[arg("x", NUM), arg("y", NUM)], builtin_lt),
+    Def::builtin(">=", &[arg("x", NUM), arg("y", NUM)], builtin_ge),
+    Def::builtin("<=", &[arg("x", NUM), arg("y", NUM)], builtin_le),
+    Def::builtin("==", &[arg("x", ANY), arg("y", ANY)], builtin_eq),
+    Def::builtin("!=", &[arg("x", ANY), arg("y", ANY)], builtin_ne),
+    Def::builtin("and", &[], builtin_and).rest(arg("xs", BOOL)),
+    Def::builtin("or", &[], builtin_or).rest(arg("xs", BOOL)),
+    Def::builtin("not", &[arg("x", BOOL)], builtin_not),
+];
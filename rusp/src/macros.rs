@@ -0,0 +1,119 @@
+//! Macros: lambdas that receive their arguments as unevaluated forms and
+//! return a form, which is evaluated in place of the call.
+//!
+//! ```text
+//! defmacro {unless2 test body} {`(if (not ,test) ,body)}
+//! ```
+//!
+//! Macros are expanded each time a call to one is evaluated. A binding
+//! that a macro introduces into its expansion should be named with
+//! `gensym`, so that it cannot capture a symbol of the caller's:
+//!
+//! ```text
+//! defmacro {or2 x y} {let {t (gensym "t")} `(let {,t ,x} (if ,t ,t ,y))}
+//! ```
+
+use crate::{
+    env::Env,
+    eval::{lval_definition, lval_run_lambda},
+    registry::{arg, Def, LIST, STR},
+    symbol::{Symbol, AMPERSAND},
+    value::{lval_err, lval_macro, lval_sexpr, lval_sym, lval_take, Lambda, LispValue, Tag},
+};
+use std::{
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Expand the macro `m` applied to the unevaluated forms in the
+/// S-expression `a`, returning the form to evaluate in place of the call.
+pub(crate) fn lval_expand(m: Lambda, a: LispValue) -> LispValue {
+    /* Unlike a lambda, a macro cannot be partially applied */
+    let variadic = m.formals.contains(&AMPERSAND);
+    let fixed = m.formals.len() - if variadic { 2 } else { 0 };
+    if a.count() < fixed || (!variadic && a.count() > fixed) {
+        return lval_err(format!(
+            "Macro passed incorrect number of arguments. Got {}, Expected {}{}.",
+            a.count(),
+            fixed,
+            if variadic { " or more" } else { "" }
+        ));
+    }
+
+    lval_run_lambda(m, a)
+}
+
+/* defmacro {name formals...} {body}: define a macro, as fun does a function */
+fn builtin_defmacro(e: &Env, a: LispValue) -> LispValue {
+    match lval_definition("defmacro", a) {
+        Ok((name, formals, body)) => {
+            e.def(name, lval_macro(e, formals, body));
+            lval_sexpr()
+        }
+        Err(err) => err,
+    }
+}
+
+/* Expand form once if it is a call to a macro bound in e, or None */
+fn lval_expand_1(e: &Env, form: &LispValue) -> Option<LispValue> {
+    let LispValue::Qexpr(cells, span) = form else {
+        return None;
+    };
+    let LispValue::Sym(sym, _) = cells.first()? else {
+        return None;
+    };
+    let LispValue::Macro(m) = e.get(sym) else {
+        return None;
+    };
+
+    let a = LispValue::Sexpr(Rc::new(cells[1..].to_vec()), span.clone());
+    match lval_expand(m, a) {
+        /* Show an S-expression as the code it is */
+        LispValue::Sexpr(cells, span) => Some(LispValue::Qexpr(cells, span)),
+        x => Some(x),
+    }
+}
+
+/* macroexpand-1 {form}: form expanded once, if it calls a macro */
+fn builtin_macroexpand_1(e: &Env, a: LispValue) -> LispValue {
+    let form = lval_take(a, 0);
+    lval_expand_1(e, &form).unwrap_or(form)
+}
+
+/* macroexpand {form}: form expanded until it no longer calls a macro */
+fn builtin_macroexpand(e: &Env, a: LispValue) -> LispValue {
+    let mut form = lval_take(a, 0);
+    while let Some(x) = lval_expand_1(e, &form) {
+        if x.tag() == Tag::Err {
+            return x;
+        }
+        form = x;
+    }
+    form
+}
+
+/* The number of symbols gensym has made, to number the next one */
+static GENSYMS: AtomicUsize = AtomicUsize::new(0);
+
+/* gensym prefix: a new uninterned symbol named after the string prefix,
+distinct from every other, even one given the same name by str->sym. Its
+name has a '#', which the reader does not allow in symbols, to tell it
+apart when printed */
+fn builtin_gensym(_e: &Env, a: LispValue) -> LispValue {
+    let LispValue::Str(prefix, _) = &a.cells()[0] else {
+        unreachable!("checked by the registry");
+    };
+    let n = GENSYMS.fetch_add(1, Ordering::Relaxed);
+    lval_sym(Symbol::uninterned(&format!("{}#{}", prefix, n)))
+}
+
+pub(crate) static MACRO_BUILTINS: &[Def] = &[
+    Def::builtin(
+        "defmacro",
+        &[arg("header", LIST), arg("body", LIST)],
+        builtin_defmacro,
+    ),
+    Def::builtin("macroexpand", &[arg("form", LIST)], builtin_macroexpand),
+    Def::builtin("macroexpand-1", &[arg("form", LIST)], builtin_macroexpand_1),
+    Def::builtin("gensym", &[arg("prefix", STR)], builtin_gensym),
+];
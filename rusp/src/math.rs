@@ -0,0 +1,270 @@
+//! Arithmetic and the other builtins operating on numbers.
+
+use crate::{
+    env::Env,
+    number::{Division, Number},
+    registry::{arg, lenv_add_def, Def, Param, NUM},
+    value::{lval_add, lval_err_arg, lval_qexpr, LispValue},
+};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::Zero;
+use std::cmp::Ordering;
+
+/* The arguments as numbers, which the registry checked they are */
+fn lval_numbers(a: &LispValue) -> Vec<Number> {
+    a.cells()
+        .iter()
+        .map(|cell| match cell {
+            LispValue::Num(x, _) => x.clone(),
+            _ => unreachable!("checked by the registry"),
+        })
+        .collect()
+}
+
+/* Fold the arguments with op. If op divides, a divisor of zero is an
+error */
+fn builtin_fold(a: LispValue, divides: bool, op: fn(Number, Number) -> Number) -> LispValue {
+    let mut nums = lval_numbers(&a).into_iter();
+    let mut x = nums.next().expect("checked by the registry");
+
+    /* Apply the operation to each remaining element in turn */
+    for (i, y) in nums.enumerate() {
+        if divides && y.is_zero() {
+            return lval_err_arg(i + 2, "Division by zero.");
+        }
+        x = op(x, y);
+    }
+    LispValue::Num(x, None)
+}
+
+fn builtin_add(_e: &Env, a: LispValue) -> LispValue {
+    builtin_fold(a, false, |x, y| x + y)
+}
+
+fn builtin_sub(_e: &Env, a: LispValue) -> LispValue {
+    /* With a single argument, negate it */
+    let negate = a.count() == 1;
+    match builtin_fold(a, false, |x, y| x - y) {
+        LispValue::Num(x, span) if negate => LispValue::Num(-x, span),
+        x => x,
+    }
+}
+
+fn builtin_mul(_e: &Env, a: LispValue) -> LispValue {
+    builtin_fold(a, false, |x, y| x * y)
+}
+
+/* '/' divides two integers exactly or truncating, as the interpreter is
+set to */
+fn builtin_div(_e: &Env, a: LispValue) -> LispValue {
+    builtin_fold(a, true, |x, y| x.div_with(y, Division::Exact))
+}
+
+fn builtin_div_truncate(_e: &Env, a: LispValue) -> LispValue {
+    builtin_fold(a, true, |x, y| x.div_with(y, Division::Truncate))
+}
+
+/* The integer division operators, for integers, rationals and floats
+alike, where q is the quotient and r the remainder:
+
+    quot, rem   q is truncated towards zero and r has the sign of x, as in C
+                and Rust: quot -7 2 is -3, rem -7 2 is -1
+    mod, %      q is rounded down, so r has the sign of y: mod -7 2 is 1,
+                mod 7 -2 is -1
+    divmod      {q r} for mod: divmod -7 2 is {-4 1}
+
+In all of them x = q * y + r. Like the other operators, they fold over
+more than two arguments: mod 17 10 4 is 3. */
+
+fn builtin_mod(_e: &Env, a: LispValue) -> LispValue {
+    builtin_fold(a, true, Number::modulo)
+}
+
+fn builtin_rem(_e: &Env, a: LispValue) -> LispValue {
+    builtin_fold(a, true, |x, y| x % y)
+}
+
+fn builtin_quot(_e: &Env, a: LispValue) -> LispValue {
+    builtin_fold(a, true, Number::quot)
+}
+
+fn builtin_divmod(_e: &Env, a: LispValue) -> LispValue {
+    let mut nums = lval_numbers(&a);
+    let y = nums.remove(1);
+    let x = nums.remove(0);
+    if y.is_zero() {
+        return lval_err_arg(2, "Division by zero.");
+    }
+
+    let q = x.clone().div_floor(y.clone());
+    let r = x.modulo(y);
+    lval_add(
+        lval_add(lval_qexpr(), LispValue::Num(q, None)),
+        LispValue::Num(r, None),
+    )
+}
+
+/* The single numeric argument */
+fn lval_number(a: &LispValue) -> Number {
+    lval_numbers(a).remove(0)
+}
+
+/* Round a float to a float, or a rational to an integer; integers are
+already whole */
+fn builtin_round_op(
+    a: LispValue,
+    round: fn(f64) -> f64,
+    round_ratio: fn(&BigRational) -> BigRational,
+) -> LispValue {
+    match lval_number(&a) {
+        Number::Float(x) => LispValue::Num(Number::Float(round(x)), None),
+        Number::Ratio(x) => LispValue::Num(Number::from(round_ratio(&x)), None),
+        x => LispValue::Num(x, None),
+    }
+}
+
+fn builtin_floor(_e: &Env, a: LispValue) -> LispValue {
+    builtin_round_op(a, f64::floor, BigRational::floor)
+}
+
+fn builtin_ceil(_e: &Env, a: LispValue) -> LispValue {
+    builtin_round_op(a, f64::ceil, BigRational::ceil)
+}
+
+fn builtin_round(_e: &Env, a: LispValue) -> LispValue {
+    /* Halfway cases round away from zero */
+    builtin_round_op(a, f64::round, BigRational::round)
+}
+
+fn builtin_sqrt(_e: &Env, a: LispValue) -> LispValue {
+    match lval_number(&a) {
+        x if x.compare(&Number::Int(0)) == Some(Ordering::Less) => {
+            lval_err_arg(1, format!("Function 'sqrt' passed negative number {}.", x))
+        }
+        x => LispValue::Num(Number::Float(x.to_f64().sqrt()), None),
+    }
+}
+
+fn builtin_abs(_e: &Env, a: LispValue) -> LispValue {
+    LispValue::Num(lval_number(&a).abs(), None)
+}
+
+/* The most bits an exact power may have, so that a typo cannot exhaust
+memory */
+const POW_MAX_BITS: u64 = 1 << 20;
+
+/* Fewer than the bits of x^y: the larger of the numerator and denominator
+of x, of b bits, is at least 2^(b - 1) */
+fn pow_bits(x: &BigRational, y: i32) -> u64 {
+    let b = x.numer().bits().max(x.denom().bits());
+    b.saturating_sub(1)
+        .saturating_mul(u64::from(y.unsigned_abs()))
+}
+
+/* pow x y: exact for an integer or rational raised to an integer */
+fn builtin_pow(_e: &Env, a: LispValue) -> LispValue {
+    let nums = lval_numbers(&a);
+
+    let x = match (nums[0].to_ratio(), &nums[1]) {
+        (Some(x), Number::Int(y)) => match i32::try_from(*y) {
+            Ok(y) if y < 0 && x.is_zero() => return lval_err_arg(2, "Division by zero."),
+            Ok(y) if pow_bits(&x, y) > POW_MAX_BITS => {
+                return lval_err_arg(
+                    2,
+                    format!(
+                        "Function 'pow' passed exponent {} too large, giving more than {} bits.",
+                        y, POW_MAX_BITS
+                    ),
+                )
+            }
+            Ok(y) => Number::from(x.pow(y)),
+            Err(_) => {
+                return lval_err_arg(
+                    2,
+                    format!("Function 'pow' passed exponent {} too large.", y),
+                )
+            }
+        },
+        (_, y) => Number::Float(nums[0].to_f64().powf(y.to_f64())),
+    };
+    LispValue::Num(x, None)
+}
+
+/* The argument that compares as `wanted` against all others */
+fn builtin_extreme(a: LispValue, wanted: Ordering) -> LispValue {
+    let mut nums = lval_numbers(&a).into_iter();
+    let mut x = nums.next().expect("checked by the registry");
+
+    for y in nums {
+        if y.compare(&x) == Some(wanted) {
+            x = y;
+        }
+    }
+    LispValue::Num(x, None)
+}
+
+fn builtin_min(_e: &Env, a: LispValue) -> LispValue {
+    builtin_extreme(a, Ordering::Less)
+}
+
+fn builtin_max(_e: &Env, a: LispValue) -> LispValue {
+    builtin_extreme(a, Ordering::Greater)
+}
+
+/* The numerator or denominator of an exact number in lowest terms */
+fn builtin_ratio_part(a: LispValue, func: &str, part: fn(&BigRational) -> &BigInt) -> LispValue {
+    let x = lval_number(&a);
+    match x.to_ratio() {
+        Some(x) => LispValue::Num(Number::from(part(&x).clone()), None),
+        None => lval_err_arg(
+            1,
+            format!("Function '{}' passed inexact number {}.", func, x),
+        ),
+    }
+}
+
+fn builtin_numerator(_e: &Env, a: LispValue) -> LispValue {
+    builtin_ratio_part(a, "numerator", BigRational::numer)
+}
+
+fn builtin_denominator(_e: &Env, a: LispValue) -> LispValue {
+    builtin_ratio_part(a, "denominator", BigRational::denom)
+}
+
+/* The parameters of the operators folding over their arguments */
+const FOLDED: &[Param] = &[arg("x", NUM)];
+const REST: Param = arg("xs", NUM);
+
+const DIVIDE: Def = Def::builtin("/", FOLDED, builtin_div).rest(REST);
+const DIVIDE_TRUNCATE: Def = Def::builtin("/", FOLDED, builtin_div_truncate).rest(REST);
+
+pub(crate) static MATH_BUILTINS: &[Def] = &[
+    Def::builtin("+", FOLDED, builtin_add).rest(REST),
+    Def::builtin("-", FOLDED, builtin_sub).rest(REST),
+    Def::builtin("*", FOLDED, builtin_mul).rest(REST),
+    DIVIDE,
+    Def::builtin("%", FOLDED, builtin_mod).rest(REST),
+    Def::builtin("mod", FOLDED, builtin_mod).rest(REST),
+    Def::builtin("rem", FOLDED, builtin_rem).rest(REST),
+    Def::builtin("quot", FOLDED, builtin_quot).rest(REST),
+    Def::builtin("divmod", &[arg("x", NUM), arg("y", NUM)], builtin_divmod),
+    Def::builtin("floor", &[arg("x", NUM)], builtin_floor),
+    Def::builtin("ceil", &[arg("x", NUM)], builtin_ceil),
+    Def::builtin("round", &[arg("x", NUM)], builtin_round),
+    Def::builtin("sqrt", &[arg("x", NUM)], builtin_sqrt),
+    Def::builtin("abs", &[arg("x", NUM)], builtin_abs),
+    Def::builtin("pow", &[arg("x", NUM), arg("y", NUM)], builtin_pow),
+    Def::builtin("min", FOLDED, builtin_min).rest(REST),
+    Def::builtin("max", FOLDED, builtin_max).rest(REST),
+    Def::builtin("numerator", &[arg("x", NUM)], builtin_numerator),
+    Def::builtin("denominator", &[arg("x", NUM)], builtin_denominator),
+];
+
+/// Bind `/`, dividing two integers as `division` says.
+pub(crate) fn lenv_add_division(e: &Env, division: Division) {
+    match division {
+        Division::Exact => lenv_add_def(e, &DIVIDE),
+        Division::Truncate => lenv_add_def(e, &DIVIDE_TRUNCATE),
+    }
+}
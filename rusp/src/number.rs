@@ -0,0 +1,508 @@
+//! The numeric tower: integers, which grow into bigints instead of
+//! overflowing, exact rationals, and floats, which any other number is
+//! promoted to when mixed with one.
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{Signed, ToPrimitive, Zero};
+use std::{
+    cmp::Ordering,
+    fmt,
+    ops::{Add, Div, Mul, Neg, Rem, Sub},
+};
+
+/// A number.
+///
+/// Arithmetic on integers and rationals is exact: an integer that does not
+/// fit in an `i64` becomes a `Big`, and a `Big` that fits again becomes an
+/// `Int`; a rational is always in lowest terms with a positive denominator
+/// other than 1, which would make it an integer. So every exact number has
+/// exactly one representation. If either operand is a float, the other is
+/// converted and the result is a float.
+#[derive(Clone, Debug)]
+pub enum Number {
+    Int(i64),
+    Big(BigInt),
+    Ratio(BigRational),
+    Float(f64),
+}
+
+/// How `/` divides two integers.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Division {
+    /// Exactly, giving a rational if the quotient is not whole.
+    #[default]
+    Exact,
+    /// Truncating the quotient towards zero, as C does.
+    Truncate,
+}
+
+impl Number {
+    /// The value as a float, rounding if it cannot be exact.
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Number::Int(x) => *x as f64,
+            Number::Big(x) => x.to_f64().unwrap_or(f64::NAN),
+            Number::Ratio(x) => x.to_f64().unwrap_or(f64::NAN),
+            Number::Float(x) => *x,
+        }
+    }
+
+    /// The value as a bigint, or `None` if it is not an integer.
+    pub fn to_bigint(&self) -> Option<BigInt> {
+        match self {
+            Number::Int(x) => Some(BigInt::from(*x)),
+            Number::Big(x) => Some(x.clone()),
+            Number::Ratio(_) | Number::Float(_) => None,
+        }
+    }
+
+    /// The value as a rational, or `None` for a float.
+    pub fn to_ratio(&self) -> Option<BigRational> {
+        match self {
+            Number::Ratio(x) => Some(x.clone()),
+            Number::Float(_) => None,
+            x => x.to_bigint().map(BigRational::from_integer),
+        }
+    }
+
+    /// Whether this is a float zero or the integer zero.
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Number::Int(x) => *x == 0,
+            Number::Big(x) => x.is_zero(),
+            Number::Ratio(x) => x.is_zero(),
+            Number::Float(x) => *x == 0.0,
+        }
+    }
+
+    /// The absolute value.
+    pub fn abs(self) -> Number {
+        match self {
+            Number::Int(x) => x
+                .checked_abs()
+                .map_or_else(|| Number::from(BigInt::from(x).abs()), Number::Int),
+            Number::Big(x) => Number::from(x.abs()),
+            Number::Ratio(x) => Number::from(x.abs()),
+            Number::Float(x) => Number::Float(x.abs()),
+        }
+    }
+
+    /// Divide, truncating the quotient towards zero. The result is an
+    /// integer unless either operand is a float. The caller checks for
+    /// division by zero.
+    pub fn quot(self, other: Number) -> Number {
+        match (self, other) {
+            (x @ Number::Float(_), y) | (x, y @ Number::Float(_)) => {
+                Number::Float((x.to_f64() / y.to_f64()).trunc())
+            }
+            (Number::Int(x), Number::Int(y)) => x.checked_div(y).map_or_else(
+                || Number::from(BigInt::from(x) / BigInt::from(y)),
+                Number::Int,
+            ),
+            (x, y) => match (x.to_bigint(), y.to_bigint()) {
+                (Some(x), Some(y)) => Number::from(x / y),
+                _ => Number::from((x / y).to_ratio_unchecked().trunc()),
+            },
+        }
+    }
+
+    /// Divide, rounding the quotient down towards negative infinity. The
+    /// result is an integer unless either operand is a float. The caller
+    /// checks for division by zero.
+    pub fn div_floor(self, other: Number) -> Number {
+        let negative = self.is_negative() != other.is_negative();
+        let exact = (self.clone() % other.clone()).is_zero();
+        let q = self.quot(other);
+        if negative && !exact {
+            q - Number::Int(1)
+        } else {
+            q
+        }
+    }
+
+    /// The remainder of `div_floor`, which has the sign of `other`: `mod -7
+    /// 2` is 1. The caller checks for division by zero.
+    pub fn modulo(self, other: Number) -> Number {
+        let r = self % other.clone();
+        if !r.is_zero() && r.is_negative() != other.is_negative() {
+            r + other
+        } else {
+            r
+        }
+    }
+
+    /* Whether the number is below zero */
+    fn is_negative(&self) -> bool {
+        self.compare(&Number::Int(0)) == Some(Ordering::Less)
+    }
+
+    /// Divide as `/` does in the given mode. The caller checks for division
+    /// by zero.
+    pub fn div_with(self, other: Number, division: Division) -> Number {
+        let integers = self.to_bigint().is_some() && other.to_bigint().is_some();
+        match division {
+            Division::Truncate if integers => self.quot(other),
+            _ => self / other,
+        }
+    }
+
+    /* The value of an exact number as a rational */
+    fn to_ratio_unchecked(&self) -> BigRational {
+        self.to_ratio().expect("an exact number")
+    }
+
+    /* Apply int_op to two integers, falling back to big_op if it overflows,
+    ratio_op if either is a rational, or float_op to both as floats */
+    fn promote(
+        self,
+        other: Number,
+        int_op: fn(i64, i64) -> Option<i64>,
+        big_op: fn(BigInt, BigInt) -> BigInt,
+        ratio_op: fn(BigRational, BigRational) -> BigRational,
+        float_op: fn(f64, f64) -> f64,
+    ) -> Number {
+        match (self, other) {
+            (Number::Int(x), Number::Int(y)) => int_op(x, y).map_or_else(
+                || Number::from(big_op(BigInt::from(x), BigInt::from(y))),
+                Number::Int,
+            ),
+            (x @ Number::Float(_), y) | (x, y @ Number::Float(_)) => {
+                Number::Float(float_op(x.to_f64(), y.to_f64()))
+            }
+            (x, y) => match (x.to_bigint(), y.to_bigint()) {
+                (Some(x), Some(y)) => Number::from(big_op(x, y)),
+                _ => Number::from(ratio_op(x.to_ratio_unchecked(), y.to_ratio_unchecked())),
+            },
+        }
+    }
+
+    /// Compare by value, promoting to floats if either is one. `None` if
+    /// either is NaN.
+    pub fn compare(&self, other: &Number) -> Option<Ordering> {
+        match (self, other) {
+            (Number::Int(x), Number::Int(y)) => Some(x.cmp(y)),
+            (Number::Float(_), _) | (_, Number::Float(_)) => {
+                self.to_f64().partial_cmp(&other.to_f64())
+            }
+            (x, y) => Some(x.to_ratio_unchecked().cmp(&y.to_ratio_unchecked())),
+        }
+    }
+}
+
+impl From<i64> for Number {
+    fn from(x: i64) -> Self {
+        Number::Int(x)
+    }
+}
+
+impl From<BigInt> for Number {
+    /// A bigint, as an `Int` if it fits.
+    fn from(x: BigInt) -> Self {
+        match x.to_i64() {
+            Some(x) => Number::Int(x),
+            None => Number::Big(x),
+        }
+    }
+}
+
+impl From<BigRational> for Number {
+    /// A rational, as an integer if it is whole.
+    fn from(x: BigRational) -> Self {
+        if x.is_integer() {
+            Number::from(x.to_integer())
+        } else {
+            Number::Ratio(x)
+        }
+    }
+}
+
+impl Add for Number {
+    type Output = Number;
+
+    fn add(self, other: Number) -> Number {
+        self.promote(
+            other,
+            i64::checked_add,
+            |x, y| x + y,
+            |x, y| x + y,
+            |x, y| x + y,
+        )
+    }
+}
+
+impl Sub for Number {
+    type Output = Number;
+
+    fn sub(self, other: Number) -> Number {
+        self.promote(
+            other,
+            i64::checked_sub,
+            |x, y| x - y,
+            |x, y| x - y,
+            |x, y| x - y,
+        )
+    }
+}
+
+impl Mul for Number {
+    type Output = Number;
+
+    fn mul(self, other: Number) -> Number {
+        self.promote(
+            other,
+            i64::checked_mul,
+            |x, y| x * y,
+            |x, y| x * y,
+            |x, y| x * y,
+        )
+    }
+}
+
+impl Div for Number {
+    type Output = Number;
+
+    /// Divide exactly, giving a rational if two integers do not divide
+    /// evenly. The caller checks for division by zero.
+    fn div(self, other: Number) -> Number {
+        match (self, other) {
+            (Number::Int(x), Number::Int(y)) if x.checked_rem(y) == Some(0) => Number::Int(x / y),
+            (x @ Number::Float(_), y) | (x, y @ Number::Float(_)) => {
+                Number::Float(x.to_f64() / y.to_f64())
+            }
+            (x, y) => Number::from(x.to_ratio_unchecked() / y.to_ratio_unchecked()),
+        }
+    }
+}
+
+impl Rem for Number {
+    type Output = Number;
+
+    /// The remainder of `quot`, which has the sign of `self`: `rem -7 2` is
+    /// -1. The caller checks for division by zero.
+    fn rem(self, other: Number) -> Number {
+        match (self, other) {
+            /* Only i64::MIN % -1 overflows, and its remainder is 0 */
+            (Number::Int(x), Number::Int(y)) => {
+                x.checked_rem(y).map_or(Number::Int(0), Number::Int)
+            }
+            (x @ Number::Float(_), y) | (x, y @ Number::Float(_)) => {
+                Number::Float(x.to_f64() % y.to_f64())
+            }
+            (x, y) => x.clone() - y.clone() * x.quot(y),
+        }
+    }
+}
+
+impl Neg for Number {
+    type Output = Number;
+
+    fn neg(self) -> Number {
+        match self {
+            Number::Int(x) => x
+                .checked_neg()
+                .map_or_else(|| Number::from(-BigInt::from(x)), Number::Int),
+            Number::Big(x) => Number::from(-x),
+            Number::Ratio(x) => Number::from(-x),
+            Number::Float(x) => Number::Float(-x),
+        }
+    }
+}
+
+impl PartialEq for Number {
+    /// Structural equality: an exact number never equals a float, and
+    /// floats are equal if they have the same bits, so that NaN equals
+    /// itself.
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Number::Int(x), Number::Int(y)) => x == y,
+            (Number::Big(x), Number::Big(y)) => x == y,
+            (Number::Ratio(x), Number::Ratio(y)) => x == y,
+            (Number::Float(x), Number::Float(y)) => x.to_bits() == y.to_bits(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Number {}
+
+impl fmt::Display for Number {
+    /// Rationals print as `n/d`, and floats always with a decimal point or
+    /// exponent, so that they read back the same.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Number::Int(x) => write!(f, "{}", x),
+            Number::Big(x) => write!(f, "{}", x),
+            Number::Ratio(x) => write!(f, "{}/{}", x.numer(), x.denom()),
+            Number::Float(x) => write!(f, "{:?}", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(x: i64) -> Number {
+        Number::Int(x)
+    }
+
+    fn big(x: &str) -> Number {
+        Number::Big(x.parse().unwrap())
+    }
+
+    fn ratio(numer: i64, denom: i64) -> Number {
+        Number::from(BigRational::new(numer.into(), denom.into()))
+    }
+
+    #[test]
+    fn overflow_promotes_to_bigints() {
+        assert_eq!(int(i64::MAX) + int(1), big("9223372036854775808"));
+        assert_eq!(int(i64::MIN) - int(1), big("-9223372036854775809"));
+        assert_eq!(int(i64::MAX) * int(2), big("18446744073709551614"));
+        assert_eq!(
+            big("18446744073709551616").to_string(),
+            "18446744073709551616"
+        );
+    }
+
+    #[test]
+    fn bigints_that_fit_become_ints() {
+        assert_eq!(big("9223372036854775808") - int(1), int(i64::MAX));
+        assert_eq!(big("-9223372036854775809") + int(1), int(i64::MIN));
+        assert_eq!(big("18446744073709551616") * int(0), int(0));
+        assert_eq!(Number::from(BigInt::from(5)), int(5));
+        assert!(matches!(
+            Number::from(BigInt::from(i64::MAX) + 1),
+            Number::Big(_)
+        ));
+    }
+
+    #[test]
+    fn min_int_edge_cases() {
+        assert_eq!(-int(i64::MIN), big("9223372036854775808"));
+        assert_eq!(-big("9223372036854775808"), int(i64::MIN));
+        assert_eq!(int(i64::MIN).abs(), big("9223372036854775808"));
+        assert_eq!(int(i64::MIN + 1).abs(), int(i64::MAX));
+    }
+
+    #[test]
+    fn floats_are_contagious() {
+        assert_eq!(
+            big("9223372036854775808") * Number::Float(0.5),
+            Number::Float(4611686018427387904.0)
+        );
+        assert_eq!(int(1) + Number::Float(0.5), Number::Float(1.5));
+        assert_eq!(
+            big("9223372036854775808").compare(&int(i64::MAX)),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(int(1).compare(&Number::Float(f64::NAN)), None);
+    }
+
+    #[test]
+    fn rationals_are_in_lowest_terms() {
+        assert_eq!(ratio(6, 4).to_string(), "3/2");
+        assert_eq!(ratio(2, -4).to_string(), "-1/2");
+        assert_eq!(ratio(4, 2), int(2));
+        assert_eq!(ratio(0, 5), int(0));
+        assert_eq!(ratio(1, 2) + ratio(1, 2), int(1));
+        assert_eq!(ratio(1, 3) * int(3), int(1));
+    }
+
+    #[test]
+    fn integers_divide_exactly() {
+        assert_eq!(int(7) / int(2), ratio(7, 2));
+        assert_eq!(int(6) / int(3), int(2));
+        assert_eq!(int(-6) / int(4), ratio(-3, 2));
+        assert_eq!(int(i64::MIN) / int(-1), big("9223372036854775808"));
+        assert_eq!(ratio(1, 2) / ratio(1, 4), int(2));
+        assert_eq!(int(1) / Number::Float(4.0), Number::Float(0.25));
+    }
+
+    #[test]
+    fn truncating_division() {
+        assert_eq!(int(7).div_with(int(2), Division::Truncate), int(3));
+        assert_eq!(int(-7).div_with(int(2), Division::Truncate), int(-3));
+        assert_eq!(int(7).div_with(int(2), Division::Exact), ratio(7, 2));
+        /* Only integers truncate */
+        assert_eq!(
+            ratio(7, 2).div_with(int(1), Division::Truncate),
+            ratio(7, 2)
+        );
+        assert_eq!(
+            Number::Float(7.0).div_with(int(2), Division::Truncate),
+            Number::Float(3.5)
+        );
+    }
+
+    #[test]
+    fn rationals_compare_exactly() {
+        assert_eq!(ratio(1, 3).compare(&ratio(2, 6)), Some(Ordering::Equal));
+        assert_eq!(ratio(-1, 2).compare(&int(0)), Some(Ordering::Less));
+        assert_eq!(
+            ratio(1, 3).compare(&Number::Float(0.3)),
+            Some(Ordering::Greater)
+        );
+        /* Equal in value, but an exact number never equals a float */
+        assert_ne!(ratio(1, 2), Number::Float(0.5));
+    }
+
+    #[test]
+    fn integer_division_signs() {
+        /* quot and rem truncate; div_floor and modulo round down */
+        let table = [
+            (7, 2, 3, 1, 3, 1),
+            (-7, 2, -3, -1, -4, 1),
+            (7, -2, -3, 1, -4, -1),
+            (-7, -2, 3, -1, 3, -1),
+            (6, -2, -3, 0, -3, 0),
+        ];
+        for (x, y, quot, rem, floor, modulo) in table {
+            assert_eq!(int(x).quot(int(y)), int(quot), "quot {} {}", x, y);
+            assert_eq!(int(x) % int(y), int(rem), "rem {} {}", x, y);
+            assert_eq!(int(x).div_floor(int(y)), int(floor), "div {} {}", x, y);
+            assert_eq!(int(x).modulo(int(y)), int(modulo), "mod {} {}", x, y);
+        }
+    }
+
+    #[test]
+    fn floored_division_agrees_with_modulo() {
+        for x in -9..=9 {
+            for y in [-4, -3, -1, 1, 3, 4] {
+                let q = int(x).div_floor(int(y));
+                let r = int(x).modulo(int(y));
+                assert_eq!(q * int(y) + r, int(x), "divmod {} {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn min_int_division() {
+        let two_63 = big("9223372036854775808");
+        assert_eq!(int(i64::MIN).quot(int(-1)), two_63);
+        assert_eq!(int(i64::MIN).div_floor(int(-1)), two_63);
+        assert_eq!(int(i64::MIN) % int(-1), int(0));
+        assert_eq!(int(i64::MIN).modulo(int(-1)), int(0));
+        assert_eq!(two_63.quot(int(-1)), int(i64::MIN));
+    }
+
+    #[test]
+    fn rational_division_signs() {
+        assert_eq!(ratio(-7, 2).quot(int(1)), int(-3));
+        assert_eq!(ratio(-7, 2).div_floor(int(1)), int(-4));
+        assert_eq!(ratio(-7, 2).modulo(int(1)), ratio(1, 2));
+        assert_eq!(ratio(7, 2).modulo(int(-1)), ratio(-1, 2));
+        assert_eq!(ratio(-7, 2) % int(1), ratio(-1, 2));
+    }
+
+    #[test]
+    fn float_division_signs() {
+        let float = Number::Float;
+        assert_eq!(float(-7.5).quot(int(2)), float(-3.0));
+        assert_eq!(float(-7.0).div_floor(float(2.0)), float(-4.0));
+        assert_eq!(float(-7.0).modulo(float(2.0)), float(1.0));
+        assert_eq!(float(7.0).modulo(float(-2.0)), float(-1.0));
+        assert_eq!(float(-7.0) % float(2.0), float(-1.0));
+    }
+}
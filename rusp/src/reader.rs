@@ -0,0 +1,485 @@
+//! A hand-written reader for the lispy grammar, replacing the mpc parser:
+//!
+//! ```text
+//! number : /-?[0-9]+(\.[0-9]+)?([eE][-+]?[0-9]+)?/ | /-?[0-9]+\/[0-9]+/ ;
+//! symbol : /[a-zA-Z0-9_+\-*\/\\=<>!&%.?]+/ ;
+//! boolean: "true" | "false" ;
+//! string : /"(\\.|[^"])*"/ ;
+//! comment: /;[^\r\n]*/ ;
+//! quote  : '`' <expr> | ',' <expr> | ",@" <expr> ;
+//! expr   : <number> | <boolean> | <symbol> | <string> | <comment> | <quote>
+//!        | <sexpr> | <qexpr> | <map> | <set> ;
+//! sexpr  : '(' <expr>* ')' ;
+//! qexpr  : '{' <expr>* '}' ;
+//! map    : '[' <expr>* ']' ;
+//! set    : "#{" <expr>* '}' ;
+//! lispy  : /^/ <expr>* /$/ ;
+//! ```
+//!
+//! Like the quotes, map and set literals are shorthand for S-expressions,
+//! calling the builtins that build them: `[k v]` reads as `(hash-map k v)`
+//! and `#{x y}` as `(hash-set x y)`, so their elements are evaluated. The
+//! empty literals `[]` and `#{}` read as the empty map and set themselves,
+//! since `(hash-map)`, like any call without arguments, would evaluate to
+//! the builtin. So in quoted data, `{[] [1 2]}` is `{[] (hash-map 1 2)}`.
+
+use crate::{
+    number::Number,
+    span::{render, Source, Span},
+    value::{
+        lval_add, lval_bool, lval_qexpr, lval_sexpr, lval_str, lval_sym, LispError, LispValue,
+    },
+};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::Zero;
+use std::{fmt, iter::Peekable, rc::Rc, str::CharIndices};
+
+/// A syntax error and where it was found.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        render(f, &self.message, &self.span)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<ParseError> for LispError {
+    fn from(err: ParseError) -> Self {
+        LispError {
+            message: err.message,
+            arg: None,
+            span: Some(err.span),
+        }
+    }
+}
+
+/* The start of an S-expression calling `form`, which shorthand reads as */
+fn lval_form(form: &str) -> LispValue {
+    lval_add(lval_sexpr(), lval_sym(form))
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_+-*/\\=<>!&%.?".contains(c)
+}
+
+/* The position of the next character: byte offset, line and column */
+#[derive(Clone, Copy)]
+struct Pos {
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+/// How deeply expressions may nest in source, so that reading them, and
+/// walking what was read, cannot overflow the native stack.
+pub(crate) const MAX_NESTING: usize = 1000;
+
+struct Reader<'a> {
+    source: Rc<Source>,
+    chars: Peekable<CharIndices<'a>>,
+    line: usize,
+    column: usize,
+    /* The number of expressions being read that the next one is inside */
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(source: &'a Rc<Source>) -> Self {
+        Self {
+            source: source.clone(),
+            chars: source.text.char_indices().peekable(),
+            line: 1,
+            column: 1,
+            depth: 0,
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|&(_, c)| c)
+    }
+
+    fn pos(&mut self) -> Pos {
+        let offset = match self.chars.peek() {
+            Some(&(offset, _)) => offset,
+            None => self.source.text.len(),
+        };
+        Pos {
+            offset,
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    /* The span from `start` up to the current position */
+    fn span(&mut self, start: Pos) -> Option<Span> {
+        Some(self.span_since(start))
+    }
+
+    /* The span of the text read since start */
+    fn span_since(&mut self, start: Pos) -> Span {
+        Span {
+            source: self.source.clone(),
+            start: start.offset,
+            end: self.pos().offset,
+            line: start.line,
+            column: start.column,
+        }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let (_, c) = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    /* Skip whitespace and comments, which run from ';' to the end of the line */
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == ';' {
+                while self.peek().is_some_and(|c| c != '\n') {
+                    self.bump();
+                }
+            } else if c.is_whitespace() {
+                self.bump();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /* An error spanning the next character */
+    fn error(&mut self, message: impl Into<String>) -> ParseError {
+        let start = self.pos();
+        let end = start.offset + self.peek().map_or(0, char::len_utf8);
+        ParseError {
+            message: message.into(),
+            span: Span {
+                source: self.source.clone(),
+                start: start.offset,
+                end,
+                line: start.line,
+                column: start.column,
+            },
+        }
+    }
+
+    /* Read expressions into x until `close` (or the end of input if None) */
+    fn read_exprs(
+        &mut self,
+        mut x: LispValue,
+        close: Option<char>,
+    ) -> Result<LispValue, ParseError> {
+        loop {
+            self.skip_whitespace();
+            match (self.peek(), close) {
+                (None, None) => return Ok(x),
+                (None, Some(close)) => return Err(self.unclosed(close)),
+                (Some(c), Some(close)) if c == close => {
+                    self.bump();
+                    return Ok(x);
+                }
+                /* Matched rather than with ?, which takes more stack here */
+                _ => match self.read_expr() {
+                    Ok(y) => x.cells_mut().push(y),
+                    err => return err,
+                },
+            }
+        }
+    }
+
+    fn read_expr(&mut self) -> Result<LispValue, ParseError> {
+        if self.depth == MAX_NESTING {
+            return Err(self.too_deep());
+        }
+        self.depth += 1;
+        let x = self.read_nested();
+        self.depth -= 1;
+        x
+    }
+
+    /* Read an expression, and any it contains. Each case is read by a
+    function of its own, to keep this frame, which every level of nesting
+    passes through, small */
+    fn read_nested(&mut self) -> Result<LispValue, ParseError> {
+        let start = self.pos();
+        let x = match self.peek() {
+            Some('(') => self.read_list(lval_sexpr(), ')'),
+            Some('{') => self.read_list(lval_qexpr(), '}'),
+            Some('[') => self.read_map(),
+            Some('#') => self.read_set(),
+            Some('"') => self.read_str(),
+            Some('`' | ',') => self.read_quote(),
+            Some(c) if is_symbol_char(c) => self.read_atom(),
+            _ => Err(self.unexpected()),
+        };
+        let span = self.span(start);
+        x.map(|x| x.with_span(span))
+    }
+
+    /* The error for the end of input before close */
+    fn unclosed(&mut self, close: char) -> ParseError {
+        self.error(format!("expected '{}' at end of input", close))
+    }
+
+    /* The error for an expression nested too deep */
+    fn too_deep(&mut self) -> ParseError {
+        self.error(format!("expressions nested more than {} deep", MAX_NESTING))
+    }
+
+    /* The error for a character, or the end of input, starting nothing */
+    fn unexpected(&mut self) -> ParseError {
+        match self.peek() {
+            Some(c) => self.error(format!("unexpected '{}'", c)),
+            None => self.error("unexpected end of input"),
+        }
+    }
+
+    /* Read the elements after an opening bracket into x, up to close */
+    fn read_list(&mut self, x: LispValue, close: char) -> Result<LispValue, ParseError> {
+        self.bump();
+        self.read_exprs(x, Some(close))
+    }
+
+    fn read_map(&mut self) -> Result<LispValue, ParseError> {
+        match self.read_list(lval_form("hash-map"), ']')? {
+            x if x.count() == 1 => Ok(LispValue::Map(Rc::default())),
+            x => Ok(x),
+        }
+    }
+
+    fn read_set(&mut self) -> Result<LispValue, ParseError> {
+        self.bump();
+        if self.peek() != Some('{') {
+            return Err(self.error("expected '{' after '#'"));
+        }
+        match self.read_list(lval_form("hash-set"), '}')? {
+            x if x.count() == 1 => Ok(LispValue::Set(Rc::default())),
+            x => Ok(x),
+        }
+    }
+
+    /* Read a quote character and the expression after it as (form expr) */
+    fn read_quote(&mut self) -> Result<LispValue, ParseError> {
+        let form = match self.bump() {
+            Some('`') => "quasiquote",
+            _ if self.peek() == Some('@') => {
+                self.bump();
+                "unquote-splicing"
+            }
+            _ => "unquote",
+        };
+        self.skip_whitespace();
+        let x = self.read_expr()?;
+        Ok(lval_add(lval_form(form), x))
+    }
+
+    fn read_str(&mut self) -> Result<LispValue, ParseError> {
+        self.bump();
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(lval_str(s)),
+                Some('\\') => s.push(self.read_escape()?),
+                Some(c) => s.push(c),
+                None => return Err(self.error("expected '\"' at end of input")),
+            }
+        }
+    }
+
+    /* The character for the escape sequence after a backslash */
+    fn read_escape(&mut self) -> Result<char, ParseError> {
+        let c = match self.peek() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some('\\') => '\\',
+            Some('"') => '"',
+            Some(c) => return Err(self.error(format!("unknown escape '\\{}'", c))),
+            None => return Err(self.error("expected '\"' at end of input")),
+        };
+        self.bump();
+        Ok(c)
+    }
+
+    fn read_atom(&mut self) -> Result<LispValue, ParseError> {
+        let start = self.pos();
+        let mut atom = String::new();
+        while let Some(c) = self.peek().filter(|&c| is_symbol_char(c)) {
+            atom.push(c);
+            self.bump();
+        }
+        match atom.as_str() {
+            "true" => Ok(lval_bool(true)),
+            "false" => Ok(lval_bool(false)),
+            _ => match lval_read_num(&atom) {
+                Some(Ok(x)) => Ok(x),
+                Some(Err(message)) => Err(ParseError {
+                    message: message.into(),
+                    span: self.span_since(start),
+                }),
+                None => Ok(lval_sym(atom)),
+            },
+        }
+    }
+}
+
+/* Skip the leading ASCII digits of s, returning how many there were */
+fn skip_digits(s: &mut &str) -> usize {
+    let n = s.bytes().take_while(u8::is_ascii_digit).count();
+    *s = &s[n..];
+    n
+}
+
+/* Whether an atom is an integer, or a float with a fraction or exponent */
+fn number_kind(atom: &str) -> Option<bool> {
+    let mut rest = atom.strip_prefix('-').unwrap_or(atom);
+    if skip_digits(&mut rest) == 0 {
+        return None;
+    }
+    let mut float = false;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        rest = fraction;
+        if skip_digits(&mut rest) == 0 {
+            return None;
+        }
+        float = true;
+    }
+    if let Some(exponent) = rest.strip_prefix(['e', 'E']) {
+        rest = exponent.strip_prefix(['-', '+']).unwrap_or(exponent);
+        if skip_digits(&mut rest) == 0 {
+            return None;
+        }
+        float = true;
+    }
+    rest.is_empty().then_some(float)
+}
+
+/* Read numer/denom as a rational in lowest terms */
+fn lval_read_ratio(numer: &str, denom: &str) -> Option<Result<LispValue, &'static str>> {
+    if number_kind(numer)? || number_kind(denom)? || denom.starts_with('-') {
+        return None;
+    }
+    let (numer, denom): (BigInt, BigInt) = (numer.parse().ok()?, denom.parse().ok()?);
+    if denom.is_zero() {
+        return Some(Err("zero denominator"));
+    }
+    Some(Ok(LispValue::Num(
+        Number::from(BigRational::new(numer, denom)),
+        None,
+    )))
+}
+
+/// Read `atom` as a number literal: an integer, as big as it needs to be,
+/// a rational `n/d`, or a float if it has a fraction or exponent. `None` if
+/// it is not a number, and an error message if it has a zero denominator.
+pub(crate) fn lval_read_num(atom: &str) -> Option<Result<LispValue, &'static str>> {
+    if let Some((numer, denom)) = atom.split_once('/') {
+        return lval_read_ratio(numer, denom);
+    }
+    let x = match number_kind(atom)? {
+        false => match atom.parse() {
+            Ok(x) => Number::Int(x),
+            Err(_) => Number::from(atom.parse::<BigInt>().ok()?),
+        },
+        true => Number::Float(atom.parse().ok()?),
+    };
+    Some(Ok(LispValue::Num(x, None)))
+}
+
+/// Read all expressions in `input` into a single sexpr.
+///
+/// Every value read carries the [`Span`] of its text in `input`, with
+/// `filename` as the name of the source. Expressions nested more than 1000
+/// deep are an error.
+pub fn lval_read(filename: &str, input: &str) -> Result<LispValue, ParseError> {
+    let source = Source::new(filename, input);
+    let mut reader = Reader::new(&source);
+    let start = reader.pos();
+    let x = reader.read_exprs(lval_sexpr(), None)?;
+    Ok(x.with_span(reader.span(start)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::lval_take;
+
+    fn read_num(atom: &str) -> Option<String> {
+        lval_read_num(atom).map(|x| x.unwrap().to_string())
+    }
+
+    #[test]
+    fn ratio_literals_are_normalised() {
+        assert_eq!(read_num("6/4").as_deref(), Some("3/2"));
+        assert_eq!(read_num("-3/6").as_deref(), Some("-1/2"));
+        assert_eq!(read_num("4/2").as_deref(), Some("2"));
+        assert_eq!(read_num("0/5").as_deref(), Some("0"));
+        assert_eq!(
+            read_num("18446744073709551616/2").as_deref(),
+            Some("9223372036854775808")
+        );
+    }
+
+    #[test]
+    fn malformed_ratios_are_symbols() {
+        for atom in ["1/-2", "1.5/2", "1/2.0", "1/", "/2", "1/2/3"] {
+            assert_eq!(read_num(atom), None, "{}", atom);
+        }
+    }
+
+    #[test]
+    fn zero_denominators_are_errors() {
+        for atom in ["1/0", "0/0", "-5/00"] {
+            assert_eq!(
+                lval_read_num(atom),
+                Some(Err("zero denominator")),
+                "{}",
+                atom
+            );
+        }
+
+        /* Even in quoted data, at the literal */
+        let err = lval_read("test", "(len {1 1/0})").unwrap_err();
+        assert_eq!(err.message, "zero denominator");
+        assert_eq!((err.span.start, err.span.end), (8, 11));
+    }
+
+    /* The string read from the literal text */
+    fn read_str(text: &str) -> Result<String, ParseError> {
+        match lval_take(lval_read("test", text)?, 0) {
+            LispValue::Str(s, _) => Ok(s),
+            x => panic!("{} is not a string", x),
+        }
+    }
+
+    #[test]
+    fn escapes_are_read() {
+        assert_eq!(read_str(r#""a\nb\tc\rd""#).unwrap(), "a\nb\tc\rd");
+        assert_eq!(read_str(r#""\"\\\0""#).unwrap(), "\"\\\0");
+        assert_eq!(read_str(r#""\\n""#).unwrap(), "\\n");
+        assert_eq!(read_str("\"é\n→\"").unwrap(), "é\n→");
+    }
+
+    #[test]
+    fn bad_escapes_are_errors() {
+        let err = read_str(r#""a\qb""#).unwrap_err();
+        assert_eq!(err.message, "unknown escape '\\q'");
+        assert_eq!((err.span.start, err.span.column), (3, 4));
+
+        let err = read_str(r#""a\"#).unwrap_err();
+        assert_eq!(err.message, "expected '\"' at end of input");
+    }
+}
@@ -0,0 +1,324 @@
+//! The registry of builtin functions and special forms.
+//!
+//! Each builtin is declared once, in a table of the module implementing it,
+//! with its signature: the name it is bound to, its parameters and the
+//! types of argument each accepts. A signature reads like a call:
+//!
+//! ```text
+//! (substr s:String start:Number [end:Number])
+//! (+ x:Number & xs:Number)
+//! ```
+//!
+//! Parameters in brackets are optional, and the one after `&` takes any
+//! number of further arguments, as for lambdas. Calls are checked against
+//! the signature before the function runs, so it can rely on what was
+//! declared, and every builtin reports a wrong call the same way. `help`
+//! gives the signature of a builtin.
+
+use crate::{
+    collections::COLLECTION_BUILTINS,
+    env::Env,
+    eval::{IO_BUILTINS, LIST_BUILTINS, VARIABLE_BUILTINS},
+    forms::SPECIAL_FORMS,
+    lists::LIST_LIBRARY,
+    logic::LOGIC_BUILTINS,
+    macros::MACRO_BUILTINS,
+    math::MATH_BUILTINS,
+    strings::STRING_BUILTINS,
+    symbol::Symbol,
+    value::{
+        lval_err, lval_err_arg, lval_err_type, lval_str, Builtin, LispValue, Special, Step, Tag,
+    },
+};
+use std::{fmt, rc::Rc};
+
+/* Every table of builtins, in the order they are bound */
+static BUILTINS: &[&[Def]] = &[
+    VARIABLE_BUILTINS,
+    LIST_BUILTINS,
+    LIST_LIBRARY,
+    MACRO_BUILTINS,
+    SPECIAL_FORMS,
+    MATH_BUILTINS,
+    LOGIC_BUILTINS,
+    STRING_BUILTINS,
+    COLLECTION_BUILTINS,
+    IO_BUILTINS,
+    HELP_BUILTINS,
+];
+
+/// The types of parameter accepting anything.
+pub(crate) const ANY: &[Tag] = &[];
+pub(crate) const BOOL: &[Tag] = &[Tag::Bool];
+pub(crate) const FUN: &[Tag] = &[Tag::Fun];
+pub(crate) const LIST: &[Tag] = &[Tag::Qexpr];
+pub(crate) const MAP: &[Tag] = &[Tag::Map];
+pub(crate) const NUM: &[Tag] = &[Tag::Num];
+pub(crate) const STR: &[Tag] = &[Tag::Str];
+
+/// How many arguments a builtin takes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+    /// From the first to the second, inclusive.
+    Between(usize, usize),
+}
+
+impl Arity {
+    /// Whether `n` arguments are accepted.
+    pub(crate) fn accepts(self, n: usize) -> bool {
+        match self {
+            Arity::Exactly(m) => n == m,
+            Arity::AtLeast(min) => n >= min,
+            Arity::Between(min, max) => (min..=max).contains(&n),
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arity::Exactly(n) => write!(f, "{}", n),
+            Arity::AtLeast(min) => write!(f, "{} or more", min),
+            Arity::Between(min, max) if *max == min + 1 => write!(f, "{} or {}", min, max),
+            Arity::Between(min, max) => write!(f, "{} to {}", min, max),
+        }
+    }
+}
+
+/// A parameter of a builtin: its name, for the signature, and the types of
+/// argument it accepts, any if there are none.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Param {
+    pub(crate) name: &'static str,
+    pub(crate) types: &'static [Tag],
+}
+
+/// The parameter `name`, accepting arguments of `types`.
+pub(crate) const fn arg(name: &'static str, types: &'static [Tag]) -> Param {
+    Param { name, types }
+}
+
+impl Param {
+    /* Check that the parameter accepts a `got` as argument `arg` of func */
+    fn check(&self, func: &str, arg: usize, got: Tag) -> Result<(), LispValue> {
+        match self.types {
+            [] => Ok(()),
+            types if types.contains(&got) => Ok(()),
+            [expected] => Err(lval_err_type(func, arg, got, *expected)),
+            [init @ .., last] => {
+                let init: Vec<&str> = init.iter().map(|tag| tag.name()).collect();
+                Err(lval_err_arg(
+                    arg,
+                    format!(
+                        "Function '{}' passed incorrect type for argument {}. Got {}, Expected {} or {}.",
+                        func,
+                        arg,
+                        got,
+                        init.join(", "),
+                        last
+                    ),
+                ))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name)?;
+        for (i, tag) in self.types.iter().enumerate() {
+            f.write_str(if i == 0 { ":" } else { "|" })?;
+            f.write_str(tag.name())?;
+        }
+        Ok(())
+    }
+}
+
+/// The function implementing a builtin.
+#[derive(Clone, Copy)]
+pub(crate) enum Func {
+    /// A function of the evaluated arguments.
+    Builtin(fn(&Env, LispValue) -> LispValue),
+    /// A special form, receiving its arguments unevaluated.
+    Special(fn(&Env, LispValue) -> Step),
+}
+
+/// A builtin as declared in a registry table. Each is a static, so one
+/// builtin value is the registry's if it points to that `Def`.
+#[derive(Clone, Copy)]
+pub(crate) struct Def {
+    pub(crate) name: &'static str,
+    pub(crate) required: &'static [Param],
+    pub(crate) optional: &'static [Param],
+    /// The parameter after `&`, taking the arguments after the others.
+    pub(crate) rest: Option<Param>,
+    pub(crate) func: Func,
+}
+
+impl fmt::Debug for Def {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Def").field(&self.name).finish()
+    }
+}
+
+impl Def {
+    /// The builtin function `name`, taking the `required` arguments.
+    pub(crate) const fn builtin(
+        name: &'static str,
+        required: &'static [Param],
+        func: fn(&Env, LispValue) -> LispValue,
+    ) -> Self {
+        Self {
+            name,
+            required,
+            optional: &[],
+            rest: None,
+            func: Func::Builtin(func),
+        }
+    }
+
+    /// The special form `name`, taking the `required` forms.
+    pub(crate) const fn special(
+        name: &'static str,
+        required: &'static [Param],
+        func: fn(&Env, LispValue) -> Step,
+    ) -> Self {
+        Self {
+            name,
+            required,
+            optional: &[],
+            rest: None,
+            func: Func::Special(func),
+        }
+    }
+
+    /// Also take the `optional` arguments after the required ones.
+    pub(crate) const fn optional(self, optional: &'static [Param]) -> Self {
+        Self { optional, ..self }
+    }
+
+    /// Also take any number of arguments after the others, as `rest`.
+    pub(crate) const fn rest(self, rest: Param) -> Self {
+        Self {
+            rest: Some(rest),
+            ..self
+        }
+    }
+
+    /// How many arguments the builtin takes.
+    pub(crate) fn arity(&self) -> Arity {
+        let min = self.required.len();
+        match (self.rest, self.optional.len()) {
+            (Some(_), _) => Arity::AtLeast(min),
+            (None, 0) => Arity::Exactly(min),
+            (None, optional) => Arity::Between(min, min + optional),
+        }
+    }
+
+    /* The parameter taking argument i, counting from 0 */
+    fn param(&self, i: usize) -> Option<&Param> {
+        self.required
+            .iter()
+            .chain(self.optional)
+            .nth(i)
+            .or(self.rest.as_ref())
+    }
+
+    /// Check the number and types of the arguments in the S-expression `a`.
+    pub(crate) fn check(&self, a: &LispValue) -> Result<(), LispValue> {
+        let arity = self.arity();
+        if !arity.accepts(a.count()) {
+            return Err(lval_err(format!(
+                "Function '{}' passed incorrect number of arguments. Got {}, Expected {}.",
+                self.name,
+                a.count(),
+                arity
+            )));
+        }
+
+        for (i, x) in a.cells().iter().enumerate() {
+            if let Some(param) = self.param(i) {
+                param.check(self.name, i + 1, x.tag())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Def {
+    /// Writes the signature, as in `(substr s:String start:Number [end:Number])`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}", self.name)?;
+        for param in self.required {
+            write!(f, " {}", param)?;
+        }
+        for param in self.optional {
+            write!(f, " [{}]", param)?;
+        }
+        if let Some(param) = &self.rest {
+            write!(f, " & {}", param)?;
+        }
+        f.write_str(")")
+    }
+}
+
+/* The formals of a lambda or macro, as in {x & xs} */
+fn formals_signature(head: &str, formals: &[Symbol]) -> String {
+    let names: Vec<&str> = formals.iter().map(|sym| sym.as_str()).collect();
+    format!("({} {{{}}})", head, names.join(" "))
+}
+
+/* help f: the signature of the builtin or special form f as a string, or
+the formals of the lambda or macro f. A function the host registered has
+no declared signature, even under the name of a builtin, and may take any
+arguments */
+fn builtin_help(_e: &Env, a: LispValue) -> LispValue {
+    let signature = match &a.cells()[0] {
+        LispValue::Fun(f) => f
+            .def
+            .map(Def::to_string)
+            .unwrap_or_else(|| format!("({} & args)", f.name)),
+        LispValue::Special(f) => f
+            .def
+            .map(Def::to_string)
+            .unwrap_or_else(|| format!("({} & forms)", f.name)),
+        LispValue::Lambda(f) => formals_signature("\\", &f.formals),
+        LispValue::Macro(m) => formals_signature("macro", &m.formals),
+        _ => unreachable!("checked by the registry"),
+    };
+    lval_str(signature)
+}
+
+static HELP_BUILTINS: &[Def] = &[Def::builtin("help", &[arg("f", FUN)], builtin_help)];
+
+/// Bind the builtin `def` in `e`, checking each call against it.
+pub(crate) fn lenv_add_def(e: &Env, def: &'static Def) {
+    let value = match def.func {
+        Func::Builtin(func) => LispValue::Fun(Builtin {
+            name: def.name.into(),
+            func: Rc::new(move |e: &Env, a: LispValue| match def.check(&a) {
+                Ok(()) => func(e, a),
+                Err(err) => err,
+            }),
+            def: Some(def),
+        }),
+        Func::Special(func) => LispValue::Special(Special {
+            name: def.name.into(),
+            func: Rc::new(move |e: &Env, a: LispValue| match def.check(&a) {
+                Ok(()) => func(e, a),
+                Err(err) => Step::Value(err),
+            }),
+            def: Some(def),
+        }),
+    };
+    e.put(def.name, value);
+}
+
+/// Bind every builtin in the registry in `e`.
+pub(crate) fn lenv_add_registry(e: &Env) {
+    for def in BUILTINS.iter().flat_map(|defs| defs.iter()) {
+        lenv_add_def(e, def);
+    }
+}
@@ -0,0 +1,172 @@
+//! Source locations, and rendering them as caret-underlined snippets.
+
+use std::{fmt, rc::Rc};
+
+/// A named piece of source text, shared by all spans read from it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Source {
+    pub name: String,
+    pub text: String,
+}
+
+impl Source {
+    pub fn new(name: impl Into<String>, text: impl Into<String>) -> Rc<Self> {
+        Rc::new(Self {
+            name: name.into(),
+            text: text.into(),
+        })
+    }
+}
+
+/// The location of an expression: the byte range `start..end` of `source`,
+/// which begins at `line` and `column` (both starting at 1).
+#[derive(Clone, PartialEq, Eq)]
+pub struct Span {
+    pub source: Rc<Source>,
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// The name of the file the span points into.
+    pub fn file(&self) -> &str {
+        &self.source.name
+    }
+
+    /// The full source line the span starts on.
+    pub fn line_text(&self) -> &str {
+        self.source.text.lines().nth(self.line - 1).unwrap_or("")
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file(), self.line, self.column)
+    }
+}
+
+impl fmt::Debug for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+/// Write `message` followed by the line `span` starts on, with the spanned
+/// text underlined:
+///
+/// ```text
+/// error: Unbound Symbol 'x'
+///  --> <stdin>:1:3
+///   |
+/// 1 | + x 1
+///   |   ^
+/// ```
+pub fn render(f: &mut fmt::Formatter<'_>, message: &str, span: &Span) -> fmt::Result {
+    let line_text = span.line_text();
+    let gutter = " ".repeat(span.line.to_string().len());
+
+    /* Underline up to the end of the first line, but at least one column */
+    let indent: String = line_text
+        .chars()
+        .take(span.column - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let rest = line_text.chars().count().saturating_sub(span.column - 1);
+    let len = span.source.text[span.start..span.end]
+        .chars()
+        .take_while(|&c| c != '\n')
+        .count()
+        .min(rest)
+        .max(1);
+
+    writeln!(f, "error: {}", message)?;
+    writeln!(f, "{}--> {}", gutter, span)?;
+    writeln!(f, "{} |", gutter)?;
+    writeln!(f, "{} | {}", span.line, line_text)?;
+    write!(f, "{} | {}{}", gutter, indent, "^".repeat(len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* The span of the first occurrence of needle in text */
+    fn span_of(text: &str, needle: &str) -> Span {
+        let start = text.find(needle).unwrap();
+        let before = &text[..start];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Span {
+            source: Source::new("test", text),
+            start,
+            end: start + needle.len(),
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+
+    struct Rendered<'a>(&'a str, &'a Span);
+
+    impl fmt::Display for Rendered<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            render(f, self.0, self.1)
+        }
+    }
+
+    fn rendered(text: &str, needle: &str) -> String {
+        Rendered("oops", &span_of(text, needle)).to_string()
+    }
+
+    #[test]
+    fn snippets_point_at_the_span() {
+        assert_eq!(
+            rendered("+ x 1", "x"),
+            "error: oops\n --> test:1:3\n  |\n1 | + x 1\n  |   ^"
+        );
+        assert_eq!(
+            rendered("(head {})", "{}"),
+            "error: oops\n --> test:1:7\n  |\n1 | (head {})\n  |       ^^"
+        );
+    }
+
+    #[test]
+    fn gutters_fit_the_line_number() {
+        let text = format!("{}(nosuch 1)", "\n".repeat(11));
+        assert_eq!(
+            rendered(&text, "nosuch"),
+            "error: oops\n  --> test:12:2\n   |\n12 | (nosuch 1)\n   |  ^^^^^^"
+        );
+    }
+
+    #[test]
+    fn carets_line_up_under_tabs_and_wide_characters() {
+        /* A tab is kept so the caret moves as far as the text does */
+        assert_eq!(
+            rendered("\t(+ 1 {})", "{}"),
+            "error: oops\n --> test:1:7\n  |\n1 | \t(+ 1 {})\n  | \t     ^^"
+        );
+        /* Columns count characters, not bytes */
+        assert_eq!(
+            rendered("(concat \"héllo→\" x)", "x)"),
+            "error: oops\n --> test:1:18\n  |\n1 | (concat \"héllo→\" x)\n  |                  ^^"
+        );
+    }
+
+    #[test]
+    fn carets_stop_at_the_end_of_the_line() {
+        assert_eq!(
+            rendered("(do (+ 1\n  2))", "(+ 1\n  2)"),
+            "error: oops\n --> test:1:5\n  |\n1 | (do (+ 1\n  |     ^^^^"
+        );
+        /* An empty span, as at the end of input, still gets one */
+        let mut span = span_of("(+ 1", "1");
+        span.start += 1;
+        span.end = span.start;
+        span.column += 1;
+        assert_eq!(
+            Rendered("oops", &span).to_string(),
+            "error: oops\n --> test:1:5\n  |\n1 | (+ 1\n  |     ^"
+        );
+    }
+}
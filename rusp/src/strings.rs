@@ -0,0 +1,138 @@
+//! Builtins operating on strings.
+
+use crate::{
+    env::Env,
+    number::Number,
+    reader::lval_read_num,
+    registry::{arg, Def, NUM, STR},
+    value::{
+        lval_add, lval_err, lval_err_arg, lval_num, lval_qexpr, lval_str, lval_sym, LispValue, Tag,
+    },
+};
+
+/* The string argument at position `arg` (starting at 1), which the
+registry checked is one */
+fn str_arg(a: &LispValue, arg: usize) -> &str {
+    match &a.cells()[arg - 1] {
+        LispValue::Str(s, _) => s,
+        _ => unreachable!("checked by the registry"),
+    }
+}
+
+/// The non-negative integer argument at position `arg` (starting at 1) of
+/// `func`.
+pub(crate) fn index_arg(func: &str, a: &LispValue, arg: usize) -> Result<usize, LispValue> {
+    match &a.cells()[arg - 1] {
+        LispValue::Num(Number::Int(n), _) => usize::try_from(*n).map_err(|_| {
+            lval_err_arg(
+                arg,
+                format!("Function '{}' passed negative index {}.", func, n),
+            )
+        }),
+        LispValue::Num(n @ (Number::Ratio(_) | Number::Float(_)), _) => Err(lval_err_arg(
+            arg,
+            format!("Function '{}' passed non-integer index {}.", func, n),
+        )),
+        LispValue::Num(n, _) => Err(lval_err_arg(
+            arg,
+            format!("Function '{}' passed index {} out of range.", func, n),
+        )),
+        _ => unreachable!("checked by the registry"),
+    }
+}
+
+fn builtin_concat(_e: &Env, a: LispValue) -> LispValue {
+    let mut joined = String::new();
+    for arg in 1..=a.count() {
+        joined.push_str(str_arg(&a, arg));
+    }
+    lval_str(joined)
+}
+
+fn builtin_len(_e: &Env, a: LispValue) -> LispValue {
+    /* The length of a string in characters, or the number of elements */
+    match &a.cells()[0] {
+        LispValue::Str(s, _) => lval_num(s.chars().count() as i64),
+        LispValue::Qexpr(cells, _) => lval_num(cells.len() as i64),
+        LispValue::Map(map) => lval_num(map.len() as i64),
+        LispValue::Set(set) => lval_num(set.len() as i64),
+        _ => unreachable!("checked by the registry"),
+    }
+}
+
+/* substr s start [end]: the characters from start up to (excluding) end */
+fn builtin_substr(_e: &Env, a: LispValue) -> LispValue {
+    let s = str_arg(&a, 1);
+    let len = s.chars().count();
+    let start = match index_arg("substr", &a, 2) {
+        Ok(start) => start,
+        Err(err) => return err,
+    };
+    let end = match a.count() {
+        3 => match index_arg("substr", &a, 3) {
+            Ok(end) => end,
+            Err(err) => return err,
+        },
+        _ => len,
+    };
+
+    if end > len || start > end {
+        return lval_err(format!(
+            "Function 'substr' passed range {}..{} out of bounds for length {}.",
+            start, end, len
+        ));
+    }
+    lval_str(s.chars().skip(start).take(end - start).collect::<String>())
+}
+
+/* split s sep: the pieces of s between occurrences of sep, or its characters
+if sep is empty */
+fn builtin_split(_e: &Env, a: LispValue) -> LispValue {
+    let (s, sep) = (str_arg(&a, 1), str_arg(&a, 2));
+
+    let pieces: Vec<String> = if sep.is_empty() {
+        s.chars().map(String::from).collect()
+    } else {
+        s.split(sep).map(String::from).collect()
+    };
+    pieces
+        .into_iter()
+        .fold(lval_qexpr(), |x, piece| lval_add(x, lval_str(piece)))
+}
+
+fn builtin_str_to_sym(_e: &Env, a: LispValue) -> LispValue {
+    lval_sym(str_arg(&a, 1))
+}
+
+fn builtin_num_to_str(_e: &Env, a: LispValue) -> LispValue {
+    lval_str(a.cells()[0].to_string())
+}
+
+fn builtin_str_to_num(_e: &Env, a: LispValue) -> LispValue {
+    let s = str_arg(&a, 1);
+    match lval_read_num(s.trim()) {
+        Some(Ok(num)) => num,
+        _ => lval_err_arg(
+            1,
+            format!("Function 'str->num' cannot read a number from \"{}\".", s),
+        ),
+    }
+}
+
+/* The types with a length */
+const SIZED: &[Tag] = &[Tag::Str, Tag::Qexpr, Tag::Map, Tag::Set];
+
+pub(crate) static STRING_BUILTINS: &[Def] = &[
+    Def::builtin("concat", &[], builtin_concat).rest(arg("strings", STR)),
+    Def::builtin("len", &[arg("x", SIZED)], builtin_len),
+    Def::builtin(
+        "substr",
+        &[arg("s", STR), arg("start", NUM)],
+        builtin_substr,
+    )
+    .optional(&[arg("end", NUM)]),
+    Def::builtin("split", &[arg("s", STR), arg("sep", STR)], builtin_split),
+    Def::builtin("str->sym", &[arg("s", STR)], builtin_str_to_sym),
+    Def::builtin("num->str", &[arg("x", NUM)], builtin_num_to_str),
+    Def::builtin("str->num", &[arg("s", STR)], builtin_str_to_num),
+];
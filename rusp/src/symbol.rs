@@ -0,0 +1,205 @@
+//! Interned symbols.
+//!
+//! The reader interns each symbol it reads in a global table, which gives
+//! every distinct name one integer ID. Environments are keyed on those IDs,
+//! so looking a symbol up, builtins included, hashes an integer instead of
+//! a string, and copying a symbol copies no string.
+//!
+//! Symbols made by `gensym` are uninterned: they get an ID of their own but
+//! are not entered in the table, so no symbol read or made by `str->sym`
+//! is ever the same one, even with the same name.
+//!
+//! Names are never freed, as in most lisps: a program creating unboundedly
+//! many symbols with `str->sym` or `gensym` keeps them all.
+
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt,
+    hash::{BuildHasherDefault, Hash, Hasher},
+    ops::Deref,
+    sync::{LazyLock, Mutex, MutexGuard, PoisonError},
+};
+
+/// An interned symbol name. Equal names are the same symbol, unless one is
+/// uninterned; symbols are compared and hashed by ID, and order by name.
+#[derive(Clone, Copy)]
+pub struct Symbol {
+    id: u32,
+    name: &'static str,
+}
+
+/* Symbols the evaluator checks for, interned first with these IDs */
+const PREDEFINED: &[Symbol] = &[AMPERSAND];
+
+/// The symbol `&`, which collects the remaining arguments of a lambda.
+pub(crate) const AMPERSAND: Symbol = Symbol { id: 0, name: "&" };
+
+/* The global symbol table, and the number of IDs given out, uninterned
+symbols included */
+struct Interner {
+    symbols: HashMap<&'static str, Symbol>,
+    ids: u32,
+}
+
+impl Interner {
+    /* A symbol named name with a fresh ID */
+    fn fresh(&mut self, name: &str) -> Symbol {
+        let id = self.ids;
+        self.ids = id.checked_add(1).expect("fewer than 2^32 symbols");
+        Symbol {
+            id,
+            name: Box::leak(name.into()),
+        }
+    }
+}
+
+static INTERNER: LazyLock<Mutex<Interner>> = LazyLock::new(|| {
+    Mutex::new(Interner {
+        symbols: PREDEFINED.iter().map(|sym| (sym.name, *sym)).collect(),
+        ids: PREDEFINED.len() as u32,
+    })
+});
+
+/* The symbol table, locked */
+fn interner() -> MutexGuard<'static, Interner> {
+    INTERNER.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl Symbol {
+    /// Intern `name`, giving the symbol for it.
+    pub fn new(name: &str) -> Self {
+        let mut interner = interner();
+        if let Some(sym) = interner.symbols.get(name) {
+            return *sym;
+        }
+        let sym = interner.fresh(name);
+        interner.symbols.insert(sym.name, sym);
+        sym
+    }
+
+    /// A new symbol named `name`, distinct from every other symbol, of that
+    /// name or not.
+    pub fn uninterned(name: &str) -> Self {
+        interner().fresh(name)
+    }
+
+    /// The integer identifying the symbol.
+    pub fn id(self) -> u32 {
+        self.id
+    }
+
+    /// The name of the symbol.
+    pub fn as_str(self) -> &'static str {
+        self.name
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.name
+    }
+}
+
+impl From<&Symbol> for Symbol {
+    fn from(sym: &Symbol) -> Self {
+        *sym
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::new(name)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Self {
+        Symbol::new(&name)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(name: &String) -> Self {
+        Symbol::new(name)
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Symbol {}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.name == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.name == *other
+    }
+}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u32(self.id);
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> Ordering {
+        /* Only uninterned symbols share a name */
+        self.name
+            .cmp(other.name)
+            .then_with(|| self.id.cmp(&other.id))
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}#{}", self.name, self.id)
+    }
+}
+
+/// Hashes symbols by their ID, which needs no hashing of its own beyond
+/// spreading the bits.
+#[derive(Default)]
+pub(crate) struct SymbolHasher(u64);
+
+impl Hasher for SymbolHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0.rotate_left(8) ^ u64::from(byte)).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        }
+    }
+
+    fn write_u32(&mut self, id: u32) {
+        self.0 = u64::from(id).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    }
+}
+
+/// A map keyed on symbols.
+pub(crate) type SymbolMap<V> = HashMap<Symbol, V, BuildHasherDefault<SymbolHasher>>;
@@ -0,0 +1,540 @@
+//! The value model shared by the reader and the evaluator.
+
+use crate::{
+    collections::Key,
+    compile::Code,
+    env::Env,
+    number::Number,
+    registry::Def,
+    span::{render, Span},
+    symbol::Symbol,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    rc::Rc,
+};
+
+/// A lispy value.
+///
+/// S- and Q-expressions, maps and sets are persistent: clones share their
+/// children or entries, so looking up a long list is cheap. Builtins like
+/// `cons` and `assoc` give a new value rather than changing the one they
+/// are passed, copying the children only if they are shared.
+///
+/// Values read from source carry the [`Span`] they were read from; values
+/// computed by the evaluator have none. Spans never affect equality.
+#[derive(Clone, Debug)]
+pub enum LispValue {
+    Num(Number, Option<Span>),
+    Bool(bool, Option<Span>),
+    Err(Box<LispError>),
+    Sym(Symbol, Option<Span>),
+    Str(String, Option<Span>),
+    Fun(Builtin),
+    Special(Special),
+    Lambda(Lambda),
+    Macro(Lambda),
+    Sexpr(Rc<Vec<LispValue>>, Option<Span>),
+    Qexpr(Rc<Vec<LispValue>>, Option<Span>),
+    Map(Rc<BTreeMap<Key, LispValue>>),
+    Set(Rc<BTreeSet<Key>>),
+}
+
+/// An error value.
+///
+/// `arg` is the position (starting at 1) of the argument a function
+/// rejected, if any. `span` is filled in by the evaluator with the location
+/// of the offending expression: that argument, or else the whole call.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LispError {
+    pub message: String,
+    pub arg: Option<usize>,
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for LispError {
+    /// Renders the message with a snippet of the source, if it is known.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.span {
+            Some(span) => render(f, &self.message, span),
+            None => write!(f, "Error: {}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for LispError {}
+
+/// The kind of a [`LispValue`], without its payload.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Tag {
+    Num,
+    Bool,
+    Err,
+    Sym,
+    Str,
+    Fun,
+    Sexpr,
+    Qexpr,
+    Map,
+    Set,
+}
+
+impl Tag {
+    /// A human readable name for error messages.
+    pub fn name(self) -> &'static str {
+        match self {
+            Tag::Num => "Number",
+            Tag::Bool => "Boolean",
+            Tag::Err => "Error",
+            Tag::Sym => "Symbol",
+            Tag::Str => "String",
+            Tag::Fun => "Function",
+            Tag::Sexpr => "S-Expression",
+            Tag::Qexpr => "Q-Expression",
+            Tag::Map => "Map",
+            Tag::Set => "Set",
+        }
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A function implemented in Rust. It receives the calling environment and
+/// its evaluated arguments as an S-expression, and returns the result or an
+/// error value.
+pub type BuiltinFn = dyn Fn(&Env, LispValue) -> LispValue;
+
+/// A named [`BuiltinFn`]. Two builtins are equal if they share the function.
+#[derive(Clone)]
+pub struct Builtin {
+    pub name: Rc<str>,
+    pub func: Rc<BuiltinFn>,
+    /// The signature, if the builtin was bound from the registry.
+    pub(crate) def: Option<&'static Def>,
+}
+
+impl PartialEq for Builtin {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.func, &other.func)
+    }
+}
+
+impl Eq for Builtin {}
+
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<builtin {}>", self.name)
+    }
+}
+
+/// What a special form evaluates to: either a value, or an expression
+/// for the evaluator to evaluate in its place. The latter is how forms
+/// evaluate their tail positions, in constant native stack.
+pub enum Step {
+    Value(LispValue),
+    Eval(Env, LispValue),
+}
+
+/// A special form implemented in Rust. Like a [`BuiltinFn`], but it
+/// receives its arguments unevaluated.
+pub type SpecialFn = dyn Fn(&Env, LispValue) -> Step;
+
+/// A named [`SpecialFn`]. Two special forms are equal if they share the
+/// function.
+#[derive(Clone)]
+pub struct Special {
+    pub name: Rc<str>,
+    pub func: Rc<SpecialFn>,
+    /// The signature, if the form was bound from the registry.
+    pub(crate) def: Option<&'static Def>,
+}
+
+impl PartialEq for Special {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.func, &other.func)
+    }
+}
+
+impl Eq for Special {}
+
+impl fmt::Debug for Special {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<special form {}>", self.name)
+    }
+}
+
+impl LispValue {
+    /// The kind of this value.
+    pub fn tag(&self) -> Tag {
+        match self {
+            LispValue::Num(..) => Tag::Num,
+            LispValue::Bool(..) => Tag::Bool,
+            LispValue::Err(_) => Tag::Err,
+            LispValue::Sym(..) => Tag::Sym,
+            LispValue::Str(..) => Tag::Str,
+            LispValue::Fun(_)
+            | LispValue::Special(_)
+            | LispValue::Lambda(_)
+            | LispValue::Macro(_) => Tag::Fun,
+            LispValue::Sexpr(..) => Tag::Sexpr,
+            LispValue::Qexpr(..) => Tag::Qexpr,
+            LispValue::Map(_) => Tag::Map,
+            LispValue::Set(_) => Tag::Set,
+        }
+    }
+
+    /// Where this value was read from, or for errors, where it arose.
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            LispValue::Num(_, span)
+            | LispValue::Bool(_, span)
+            | LispValue::Sym(_, span)
+            | LispValue::Str(_, span)
+            | LispValue::Sexpr(_, span)
+            | LispValue::Qexpr(_, span) => span.as_ref(),
+            LispValue::Err(err) => err.span.as_ref(),
+            LispValue::Fun(_)
+            | LispValue::Special(_)
+            | LispValue::Lambda(_)
+            | LispValue::Macro(_)
+            | LispValue::Map(_)
+            | LispValue::Set(_) => None,
+        }
+    }
+
+    /// Replace the span of this value, or of the error it is.
+    pub fn with_span(mut self, span: Option<Span>) -> Self {
+        match &mut self {
+            LispValue::Num(_, old)
+            | LispValue::Bool(_, old)
+            | LispValue::Sym(_, old)
+            | LispValue::Str(_, old)
+            | LispValue::Sexpr(_, old)
+            | LispValue::Qexpr(_, old) => *old = span,
+            LispValue::Err(err) => err.span = span,
+            LispValue::Fun(_)
+            | LispValue::Special(_)
+            | LispValue::Lambda(_)
+            | LispValue::Macro(_)
+            | LispValue::Map(_)
+            | LispValue::Set(_) => {}
+        }
+        self
+    }
+
+    /// The children of an S- or Q-expression, empty for everything else.
+    pub fn cells(&self) -> &[LispValue] {
+        match self {
+            LispValue::Sexpr(cells, _) | LispValue::Qexpr(cells, _) => cells,
+            _ => &[],
+        }
+    }
+
+    /// The children of an S- or Q-expression, copied first if they are
+    /// shared. Panics for any other value.
+    pub fn cells_mut(&mut self) -> &mut Vec<LispValue> {
+        match self {
+            LispValue::Sexpr(cells, _) | LispValue::Qexpr(cells, _) => Rc::make_mut(cells),
+            other => panic!("{:?} has no cells", other.tag()),
+        }
+    }
+
+    /// The number of children, see [`LispValue::cells`].
+    pub fn count(&self) -> usize {
+        self.cells().len()
+    }
+}
+
+/// A user-defined function.
+///
+/// `env` is the scope the lambda was created in, extended with the
+/// arguments of any earlier partial application. Calls bind `formals` in a
+/// new scope on top of it, so lambdas close over their lexical environment.
+/// A formal `&` followed by a single symbol collects the remaining arguments
+/// into a Q-expression. Copies of a lambda share its formals and body, and
+/// the bytecode the body is compiled to when first called by the
+/// [`Evaluator::Bytecode`](crate::Evaluator::Bytecode).
+///
+/// A [`LispValue::Macro`] is a lambda too, but one called with its
+/// arguments unevaluated, and whose result is evaluated in place of the
+/// call.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lambda {
+    pub env: Env,
+    pub formals: Rc<[Symbol]>,
+    pub body: Rc<LispValue>,
+    pub(crate) code: Code,
+}
+
+impl PartialEq for LispValue {
+    /// Structural equality, ignoring spans.
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (LispValue::Num(x, _), LispValue::Num(y, _)) => x == y,
+            (LispValue::Bool(x, _), LispValue::Bool(y, _)) => x == y,
+            (LispValue::Err(x), LispValue::Err(y)) => x.message == y.message,
+            (LispValue::Sym(x, _), LispValue::Sym(y, _)) => x == y,
+            (LispValue::Str(x, _), LispValue::Str(y, _)) => x == y,
+            (LispValue::Fun(x), LispValue::Fun(y)) => x == y,
+            (LispValue::Special(x), LispValue::Special(y)) => x == y,
+            (LispValue::Lambda(x), LispValue::Lambda(y)) => x == y,
+            (LispValue::Macro(x), LispValue::Macro(y)) => x == y,
+            (LispValue::Sexpr(x, _), LispValue::Sexpr(y, _)) => x == y,
+            (LispValue::Qexpr(x, _), LispValue::Qexpr(y, _)) => x == y,
+            (LispValue::Map(x), LispValue::Map(y)) => x == y,
+            (LispValue::Set(x), LispValue::Set(y)) => x == y,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for LispValue {}
+
+/// Construct an integer.
+pub fn lval_num(num: i64) -> LispValue {
+    LispValue::Num(Number::Int(num), None)
+}
+
+/// Construct a float.
+pub fn lval_float(num: f64) -> LispValue {
+    LispValue::Num(Number::Float(num), None)
+}
+
+/// Construct a boolean.
+pub fn lval_bool(b: bool) -> LispValue {
+    LispValue::Bool(b, None)
+}
+
+/// Construct an error.
+pub fn lval_err(err: impl Into<String>) -> LispValue {
+    LispValue::Err(Box::new(LispError {
+        message: err.into(),
+        arg: None,
+        span: None,
+    }))
+}
+
+/// Construct an error about the argument at position `arg` (starting at 1).
+pub fn lval_err_arg(arg: usize, err: impl Into<String>) -> LispValue {
+    LispValue::Err(Box::new(LispError {
+        message: err.into(),
+        arg: Some(arg),
+        span: None,
+    }))
+}
+
+/// Construct the error for `func` being passed a `got` at position `arg`
+/// where it expects an `expected`.
+pub fn lval_err_type(func: &str, arg: usize, got: Tag, expected: Tag) -> LispValue {
+    lval_err_arg(
+        arg,
+        format!(
+            "Function '{}' passed incorrect type for argument {}. Got {}, Expected {}.",
+            func, arg, got, expected
+        ),
+    )
+}
+
+/// Construct the error for `func` being passed `got` arguments where it
+/// expects `expected`.
+pub fn lval_err_count(func: &str, got: usize, expected: usize) -> LispValue {
+    lval_err(format!(
+        "Function '{}' passed incorrect number of arguments. Got {}, Expected {}.",
+        func, got, expected
+    ))
+}
+
+/// Construct a new symbol.
+pub fn lval_sym(sym: impl Into<Symbol>) -> LispValue {
+    LispValue::Sym(sym.into(), None)
+}
+
+/// Construct a new string.
+pub fn lval_str(s: impl Into<String>) -> LispValue {
+    LispValue::Str(s.into(), None)
+}
+
+/// Construct a builtin function value.
+pub fn lval_fun(
+    name: impl Into<Rc<str>>,
+    func: impl Fn(&Env, LispValue) -> LispValue + 'static,
+) -> LispValue {
+    LispValue::Fun(Builtin {
+        name: name.into(),
+        func: Rc::new(func),
+        def: None,
+    })
+}
+
+/// Construct a special form value.
+pub fn lval_special(
+    name: impl Into<Rc<str>>,
+    func: impl Fn(&Env, LispValue) -> Step + 'static,
+) -> LispValue {
+    LispValue::Special(Special {
+        name: name.into(),
+        func: Rc::new(func),
+        def: None,
+    })
+}
+
+/// Construct a lambda closing over `env`.
+pub fn lval_lambda(env: &Env, formals: Vec<Symbol>, body: LispValue) -> LispValue {
+    LispValue::Lambda(Lambda {
+        env: env.clone(),
+        formals: formals.into(),
+        body: Rc::new(body),
+        code: Code::default(),
+    })
+}
+
+/// Construct a macro closing over `env`.
+pub fn lval_macro(env: &Env, formals: Vec<Symbol>, body: LispValue) -> LispValue {
+    LispValue::Macro(Lambda {
+        env: env.clone(),
+        formals: formals.into(),
+        body: Rc::new(body),
+        code: Code::default(),
+    })
+}
+
+/// Construct new empty sexpr.
+pub fn lval_sexpr() -> LispValue {
+    LispValue::Sexpr(Rc::default(), None)
+}
+
+/// Construct new empty qexpr.
+pub fn lval_qexpr() -> LispValue {
+    LispValue::Qexpr(Rc::default(), None)
+}
+
+/// Append x to the children of val.
+pub fn lval_add(mut val: LispValue, x: LispValue) -> LispValue {
+    val.cells_mut().push(x);
+    val
+}
+
+/// Remove the item at i, shifting the rest over.
+pub fn lval_pop(v: &mut LispValue, i: usize) -> LispValue {
+    v.cells_mut().remove(i)
+}
+
+/// Remove the item at i and drop the rest of v. If the children of v are
+/// shared, only the item is copied.
+pub fn lval_take(v: LispValue, i: usize) -> LispValue {
+    match v {
+        LispValue::Sexpr(cells, _) | LispValue::Qexpr(cells, _) => match Rc::try_unwrap(cells) {
+            Ok(mut cells) => cells.swap_remove(i),
+            Err(cells) => cells[i].clone(),
+        },
+        other => panic!("{:?} has no cells", other.tag()),
+    }
+}
+
+/// Move all children of y to the end of x.
+pub fn lval_join(mut x: LispValue, mut y: LispValue) -> LispValue {
+    x.cells_mut().append(y.cells_mut());
+    x
+}
+
+/* Print an lispval */
+fn lval_expr_print<T: fmt::Display>(
+    f: &mut fmt::Formatter<'_>,
+    cells: &[T],
+    open: &str,
+    close: &str,
+) -> fmt::Result {
+    write!(f, "{}", open)?;
+    for (i, cell) in cells.iter().enumerate() {
+        if i != 0 {
+            write!(f, " ")?;
+        }
+        write!(f, "{}", cell)?;
+    }
+    write!(f, "{}", close)
+}
+
+/* Print a string in quotes, escaped so that it reads back the same */
+fn lval_str_print(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '\n' => write!(f, "\\n")?,
+            '\t' => write!(f, "\\t")?,
+            '\r' => write!(f, "\\r")?,
+            '\0' => write!(f, "\\0")?,
+            '\\' => write!(f, "\\\\")?,
+            '"' => write!(f, "\\\"")?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for LispValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LispValue::Num(num, _) => write!(f, "{}", num),
+            LispValue::Bool(b, _) => write!(f, "{}", b),
+            LispValue::Err(err) => write!(f, "Error: {}", err.message),
+            LispValue::Sym(sym, _) => write!(f, "{}", sym),
+            LispValue::Str(s, _) => lval_str_print(f, s),
+            LispValue::Fun(_) => write!(f, "<builtin>"),
+            LispValue::Special(_) => write!(f, "<special form>"),
+            LispValue::Lambda(l) => {
+                write!(f, "(\\ ")?;
+                lval_expr_print(f, &l.formals, "{", "}")?;
+                write!(f, " {})", l.body)
+            }
+            LispValue::Macro(m) => {
+                write!(f, "(macro ")?;
+                lval_expr_print(f, &m.formals, "{", "}")?;
+                write!(f, " {})", m.body)
+            }
+            LispValue::Sexpr(cells, _) => lval_expr_print(f, cells, "(", ")"),
+            LispValue::Qexpr(cells, _) => lval_expr_print(f, cells, "{", "}"),
+            LispValue::Map(map) => {
+                let cells: Vec<&LispValue> = map.iter().flat_map(|(k, v)| [k.value(), v]).collect();
+                lval_expr_print(f, &cells, "[", "]")
+            }
+            LispValue::Set(set) => {
+                let cells: Vec<&LispValue> = set.iter().map(Key::value).collect();
+                lval_expr_print(f, &cells, "#{", "}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::lval_read;
+
+    #[test]
+    fn strings_print_escaped() {
+        assert_eq!(lval_str("a\nb\tc\rd").to_string(), r#""a\nb\tc\rd""#);
+        assert_eq!(lval_str("\"\\\0").to_string(), r#""\"\\\0""#);
+        assert_eq!(lval_str("é→").to_string(), "\"é→\"");
+    }
+
+    #[test]
+    fn printed_strings_read_back_the_same() {
+        for s in [
+            "",
+            "plain",
+            "a\nb\tc\rd",
+            "\"quoted\"",
+            "back\\slash\\n",
+            "\0é→\n",
+        ] {
+            let printed = lval_str(s).to_string();
+            let read = lval_take(lval_read("test", &printed).unwrap(), 0);
+            assert_eq!(read, lval_str(s), "{}", printed);
+        }
+    }
+}
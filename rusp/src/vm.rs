@@ -0,0 +1,341 @@
+//! A stack machine running the bytecode of the [`compile`](crate::compile)r,
+//! as an alternative to the tree-walking evaluator.
+//!
+//! Each lambda call made from bytecode pushes a frame, with the lambda's
+//! body compiled once and shared by all its copies, and a call in tail
+//! position replaces the caller's frame instead. Special forms that are not
+//! compiled inline, and macros, are applied as by the tree-walker, and what
+//! they leave to evaluate is compiled when they do.
+//!
+//! Builtins still receive and return values, and call back into
+//! [`lval_eval`](crate::eval::lval_eval), which runs bytecode again while
+//! the environment is set to [`Evaluator::Bytecode`].
+
+use crate::{
+    compile::{compile, Chunk, Op},
+    env::{DepthGuard, Env},
+    eval::{lval_bind, lval_call, lval_function, lval_locate},
+    macros::lval_expand,
+    span::Span,
+    value::{lval_err, lval_err_type, Lambda, LispValue, Step, Tag},
+};
+use std::{mem, ptr, rc::Rc};
+
+/// How an interpreter evaluates expressions. Both give the same values and
+/// errors, though the bytecode may nest somewhat deeper before reaching the
+/// maximum depth.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Evaluator {
+    /// Walk the expressions as read, the reference evaluator.
+    #[default]
+    TreeWalker,
+    /// Compile the expressions to bytecode and run it on a stack machine.
+    Bytecode,
+}
+
+/* A chunk being run */
+struct Frame {
+    chunk: Rc<Chunk>,
+    pc: usize,
+    env: Env,
+    /* Where the frame's values start on the stack */
+    base: usize,
+    /* The scopes to go back to at each LeaveScope */
+    outer: Vec<Env>,
+    /* The level of depth a frame pushed for a call takes */
+    _guard: Option<DepthGuard>,
+}
+
+struct Vm {
+    stack: Vec<LispValue>,
+    frames: Vec<Frame>,
+}
+
+/// Evaluate `v` in `e` by compiling it to bytecode.
+///
+/// Never inlined, so the machine's locals stay out of the frames of the
+/// tree-walker, which calls this on each level of its recursion.
+#[inline(never)]
+pub(crate) fn vm_eval(e: &Env, v: LispValue) -> LispValue {
+    match v {
+        LispValue::Sym(sym, span) => match e.get(sym) {
+            err @ LispValue::Err(_) => err.with_span(span),
+            x => x,
+        },
+        LispValue::Sexpr(..) => vm_run(e.clone(), Rc::new(compile(v))),
+        v => v,
+    }
+}
+
+/// Apply the lambda `f` to the arguments in the S-expression `a`, and run
+/// its body's bytecode to the end.
+#[inline(never)]
+pub(crate) fn vm_call(f: Lambda, a: LispValue) -> LispValue {
+    let chunk = f.code.get_or_compile(&f.body);
+    match lval_bind(&f, a) {
+        Ok(scope) => {
+            let Some(_guard) = scope.enter() else {
+                return lval_err_depth(&scope, None);
+            };
+            vm_run(scope, chunk)
+        }
+        Err(x) => x,
+    }
+}
+
+fn lval_err_depth(e: &Env, span: Option<Span>) -> LispValue {
+    lval_err(format!(
+        "Recursion depth exceeded. Evaluation may nest at most {} deep.",
+        e.max_depth()
+    ))
+    .with_span(span)
+}
+
+/* Run chunk in env until it returns */
+fn vm_run(env: Env, chunk: Rc<Chunk>) -> LispValue {
+    let mut vm = Vm {
+        stack: Vec::new(),
+        frames: vec![Frame {
+            chunk,
+            pc: 0,
+            env,
+            base: 0,
+            outer: Vec::new(),
+            _guard: None,
+        }],
+    };
+    loop {
+        if let Some(x) = vm.step() {
+            return x;
+        }
+    }
+}
+
+impl Vm {
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("running a frame")
+    }
+
+    fn push(&mut self, x: LispValue) {
+        self.stack.push(x);
+    }
+
+    fn pop(&mut self) -> LispValue {
+        self.stack.pop().expect("an operand on the stack")
+    }
+
+    /* Run one instruction, returning the value of the outermost frame once
+    it returns */
+    fn step(&mut self) -> Option<LispValue> {
+        let frame = self.frames.last_mut().expect("running a frame");
+        let op = frame.chunk.code[frame.pc];
+        frame.pc += 1;
+        match op {
+            Op::Const(x) => {
+                let x = frame.chunk.consts[x].clone();
+                self.push(x);
+            }
+            Op::Load(sym) => {
+                let LispValue::Sym(sym, span) = &frame.chunk.consts[sym] else {
+                    unreachable!("loading a symbol");
+                };
+                let x = match frame.env.get(sym) {
+                    err @ LispValue::Err(_) => err.with_span(span.clone()),
+                    x => x,
+                };
+                self.push(x);
+            }
+            Op::Function(sym) => {
+                let LispValue::Sym(sym, span) = &frame.chunk.consts[sym] else {
+                    unreachable!("loading a symbol");
+                };
+                let f = lval_function(&frame.env, *sym, span.clone());
+                self.push(f);
+            }
+            Op::Head { call, end, tail } => {
+                if matches!(
+                    self.stack.last(),
+                    Some(LispValue::Special(_) | LispValue::Macro(_))
+                ) {
+                    frame.pc = end;
+                    let f = self.pop();
+                    self.head(f, call, tail);
+                }
+            }
+            Op::Call { call, tail } => self.call(call, tail),
+            Op::Inline { def, generic } => match self.stack.last() {
+                Some(LispValue::Special(form)) if form.def.is_some_and(|d| ptr::eq(d, def)) => {
+                    self.pop();
+                }
+                _ => frame.pc = generic,
+            },
+            Op::Test {
+                func,
+                call,
+                when,
+                skip,
+                end,
+            } => match self.pop() {
+                LispValue::Bool(b, _) => {
+                    if b != when {
+                        self.frame().pc = skip;
+                    }
+                }
+                err @ LispValue::Err(_) => self.fail(err, call, end),
+                other => {
+                    let span = self.frame().chunk.calls[call].spans[1].clone();
+                    self.fail(
+                        lval_err_type(func, 1, other.tag(), Tag::Bool).with_span(span),
+                        call,
+                        end,
+                    );
+                }
+            },
+            Op::Discard { call, end } => {
+                if let err @ LispValue::Err(_) = self.pop() {
+                    self.fail(err, call, end);
+                }
+            }
+            Op::EnterScope => {
+                let scope = Env::with_parent(&frame.env);
+                let outer = mem::replace(&mut frame.env, scope);
+                frame.outer.push(outer);
+            }
+            Op::Bind { sym, call, end } => {
+                let LispValue::Sym(sym, _) = &frame.chunk.consts[sym] else {
+                    unreachable!("binding a symbol");
+                };
+                let sym = *sym;
+                match self.pop() {
+                    err @ LispValue::Err(_) => self.fail(err, call, end),
+                    value => self.frame().env.put(sym, value),
+                }
+            }
+            Op::LeaveScope => {
+                frame.env = frame.outer.pop().expect("leaving a scope entered");
+            }
+            Op::Jump(target) => frame.pc = target,
+            Op::Return => {
+                let x = self.pop();
+                let frame = self.frames.pop().expect("returning from a frame");
+                self.stack.truncate(frame.base);
+                if self.frames.is_empty() {
+                    return Some(x);
+                }
+                self.push(x);
+            }
+        }
+        None
+    }
+
+    /* Leave the error err of an inline form as its value, located as the
+    form would, and continue at end */
+    fn fail(&mut self, err: LispValue, call: usize, end: usize) {
+        let frame = self.frame();
+        frame.pc = end;
+        let call = &frame.chunk.calls[call];
+        let err = lval_locate(err, &call.spans, call.span.clone());
+        self.push(err);
+    }
+
+    /* Apply the special form or macro f to the arguments of call as read */
+    fn head(&mut self, f: LispValue, call: usize, tail: bool) {
+        let frame = self.frame();
+        let env = frame.env.clone();
+        let chunk = frame.chunk.clone();
+        let call = &chunk.calls[call];
+        let a = LispValue::Sexpr(call.args.clone(), call.span.clone());
+        match f {
+            LispValue::Special(form) => match (form.func)(&env, a) {
+                Step::Value(x) => self.push(lval_locate(x, &call.spans, call.span.clone())),
+                Step::Eval(env, x) => self.eval(env, x, tail, call.span.clone()),
+            },
+            LispValue::Macro(m) => match lval_expand(m, a) {
+                err @ LispValue::Err(_) => {
+                    self.push(lval_locate(err, &call.spans, call.span.clone()))
+                }
+                /* Evaluate the expansion in place of the call */
+                LispValue::Qexpr(cells, span) => {
+                    self.eval(env, LispValue::Sexpr(cells, span), tail, call.span.clone())
+                }
+                x => self.eval(env, x, tail, call.span.clone()),
+            },
+            _ => unreachable!("checked by Head"),
+        }
+    }
+
+    /* Pop the arguments and first element of call, and apply it */
+    fn call(&mut self, call: usize, tail: bool) {
+        let frame = self.frame();
+        let env = frame.env.clone();
+        let chunk = frame.chunk.clone();
+        let call = &chunk.calls[call];
+        let args = self.stack.split_off(self.stack.len() - call.args.len());
+        let f = self.pop();
+
+        /* Error Checking */
+        if f.tag() == Tag::Err {
+            return self.push(f);
+        }
+        if let Some(i) = args.iter().position(|arg| arg.tag() == Tag::Err) {
+            return self.push(args.into_iter().nth(i).expect("found"));
+        }
+        /* Single Expression */
+        if args.is_empty() {
+            return self.push(f);
+        }
+
+        let a = LispValue::Sexpr(Rc::new(args), call.span.clone());
+        let step = match f {
+            LispValue::Lambda(f) => {
+                let code = f.code.get_or_compile(&f.body);
+                match lval_bind(&f, a) {
+                    Ok(scope) => return self.enter(scope, code, tail, call.span.clone()),
+                    Err(x) => Step::Value(x),
+                }
+            }
+            f => lval_call(&env, f, a),
+        };
+        match step {
+            Step::Value(x) => self.push(lval_locate(x, &call.spans, call.span.clone())),
+            Step::Eval(env, x) => self.eval(env, x, tail, call.span.clone()),
+        }
+    }
+
+    /* Evaluate x in env as the value of the current instruction */
+    fn eval(&mut self, env: Env, x: LispValue, tail: bool, span: Option<Span>) {
+        match x {
+            LispValue::Sym(sym, span) => match env.get(sym) {
+                err @ LispValue::Err(_) => self.push(err.with_span(span)),
+                x => self.push(x),
+            },
+            LispValue::Sexpr(..) => self.enter(env, Rc::new(compile(x)), tail, span),
+            x => self.push(x),
+        }
+    }
+
+    /* Run chunk in env, in place of the current frame in tail position, or
+    else in a new frame whose value is pushed when it returns */
+    fn enter(&mut self, env: Env, chunk: Rc<Chunk>, tail: bool, span: Option<Span>) {
+        if tail {
+            let frame = self.frames.last_mut().expect("running a frame");
+            self.stack.truncate(frame.base);
+            frame.chunk = chunk;
+            frame.pc = 0;
+            frame.env = env;
+            frame.outer.clear();
+            return;
+        }
+        let Some(guard) = env.enter() else {
+            return self.push(lval_err_depth(&env, span));
+        };
+        self.frames.push(Frame {
+            chunk,
+            pc: 0,
+            env,
+            base: self.stack.len(),
+            outer: Vec::new(),
+            _guard: Some(guard),
+        });
+    }
+}
@@ -0,0 +1,500 @@
+//! Cross-checks the bytecode evaluator against the tree-walker: each script
+//! must give the same value, or the same error at the same place, with
+//! either, and collecting garbage must never reclaim a scope either one is
+//! still using.
+
+use rusp::{
+    lval_num, lval_read, lval_special, Env, Evaluator, Interpreter, Step, DEFAULT_MAX_DEPTH,
+    STACK_PER_DEPTH,
+};
+use std::thread;
+
+const EVALUATORS: [Evaluator; 2] = [Evaluator::TreeWalker, Evaluator::Bytecode];
+
+/* The value or error of script, as printed */
+fn eval(interpreter: &mut Interpreter, script: &str) -> String {
+    match interpreter.eval_source("test.lspy", script) {
+        Ok(value) => value.to_string(),
+        Err(err) => format!("error: {}", err),
+    }
+}
+
+/* An interpreter using evaluator, with nothing yet to collect */
+fn interpreter(evaluator: Evaluator) -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_evaluator(evaluator);
+    interpreter.collect_garbage();
+    interpreter
+}
+
+/* The value or error of script in a new interpreter */
+fn run(evaluator: Evaluator, script: &str) -> String {
+    eval(&mut interpreter(evaluator), script)
+}
+
+/* Run script with both evaluators, and give what both gave */
+fn check(script: &str) -> String {
+    let tree = run(Evaluator::TreeWalker, script);
+    let bytecode = run(Evaluator::Bytecode, script);
+    assert_eq!(tree, bytecode, "evaluators disagree on {:?}", script);
+    tree
+}
+
+#[test]
+fn arithmetic() {
+    assert_eq!(check("(+ 1 (* 2 3))"), "7");
+    assert_eq!(check("(/ 7 2)"), "7/2");
+    assert_eq!(check("(- (* 1.5 2) 1)"), "2.0");
+    assert_eq!(check("(mod -7 2)"), "1");
+    assert_eq!(check("(% 7 -2)"), "-1");
+    assert_eq!(check("(divmod -7 2)"), "{-4 1}");
+    assert_eq!(check("(pow 2/3 -2)"), "9/4");
+    assert_eq!(check("(pow -1 2000000001)"), "-1");
+    assert_eq!(check("(> (pow 2 1048576) 0)"), "true");
+    assert!(check("(pow 3 2000000000)").contains("giving more than 1048576 bits"));
+    assert!(check("(pow 1/2 -1048577)").contains("giving more than 1048576 bits"));
+    check("(+ 1 {})");
+    check("(/ 1 0)");
+}
+
+#[test]
+fn expressions() {
+    assert_eq!(check("()"), "()");
+    assert_eq!(check("(5)"), "5");
+    assert_eq!(check("((+))"), "<builtin>");
+    assert_eq!(check("({1 2})"), "{1 2}");
+    check("(undefined 1 2)");
+    check("(1 2 3)");
+    check("(+ 1 undefined (print \"still evaluated\"))");
+}
+
+#[test]
+fn definitions() {
+    assert_eq!(check("(def {x y} 1 2) (+ x y)"), "3");
+    assert_eq!(check("(def {f} (\\ {x} {* x 2})) (f 21)"), "42");
+    assert_eq!(check("(def {x} 1) ((\\ {_} {= {x} 2}) 0) x"), "1");
+    assert_eq!(check("(def {x} 1) ((\\ {_} {def {x} 2}) 0) x"), "2");
+    assert!(check("(def {x} 1 2)")
+        .contains("Function 'def' passed too many values for symbols. Got 2, Expected 1."));
+    assert!(check("(def {x y} 1)")
+        .contains("Function 'def' passed too few values for symbols. Got 1, Expected 2."));
+    assert!(check("(= {x y} 1)")
+        .contains("Function '=' passed too few values for symbols. Got 1, Expected 2."));
+    check("(def {1} 2)");
+    check("(\\ {x 1} {x})");
+}
+
+#[test]
+fn lambdas() {
+    assert_eq!(check("(fun {add a b} {+ a b}) (add 1 2)"), "3");
+    assert_eq!(check("(fun {add a b} {+ a b}) ((add 1) 2)"), "3");
+    assert_eq!(check("(fun {add a b} {+ a b}) (add 1)"), "(\\ {b} {+ a b})");
+    assert_eq!(check("(fun {args & xs} {xs}) (args 1 2 3)"), "{1 2 3}");
+    assert_eq!(check("(fun {args x & xs} {xs}) (args 1)"), "{}");
+    check("(fun {add a b} {+ a b}) (add 1 2 3)");
+    check("(fun {bad a} {+ a {}}) (bad 1)");
+    assert_eq!(
+        check("(fun {adder n} {\\ {x} {+ x n}}) (def {add5} (adder 5)) (add5 10)"),
+        "15"
+    );
+    assert_eq!(
+        check("(fun {counter n} {do (= {m} (+ n 1)) (\\ {_} {m})}) ((counter 1) 0)"),
+        "2"
+    );
+}
+
+#[test]
+fn recursion() {
+    let fib = "(fun {fib n} {if (< n 2) {n} {+ (fib (- n 1)) (fib (- n 2))}})";
+    assert_eq!(check(&format!("{} (fib 15)", fib)), "610");
+    let sum = "(fun {sum n acc} {if (== n 0) {acc} {sum (- n 1) (+ acc n)}})";
+    assert_eq!(check(&format!("{} (sum 100000 0)", sum)), "5000050000");
+    let count = "(fun {count n} {let {m n} {cond {(== m 0) 0} {true (count (- m 1))}}})";
+    assert_eq!(check(&format!("{} (count 20000)", count)), "0");
+    let even = "(fun {even n} {if (== n 0) {true} {odd (- n 1)}}) \
+                (fun {odd n} {if (== n 0) {false} {even (- n 1)}})";
+    assert_eq!(check(&format!("{} (even 50001)", even)), "false");
+}
+
+#[test]
+fn conditionals() {
+    assert_eq!(check("(if true {1} {2})"), "1");
+    assert_eq!(check("(if false {1} {2})"), "2");
+    assert_eq!(check("(if false {1})"), "()");
+    assert_eq!(check("(if true 1 2)"), "1");
+    assert_eq!(check("(def {x} 3) (if true x 2)"), "3");
+    assert_eq!(check("(if true {} {2})"), "()");
+    check("(if 1 {1} {2})");
+    check("(if (+ 1 {}) {1} {2})");
+    check("(if true)");
+    check("(if true {1} {2} {3})");
+    assert_eq!(check("(when true 1 2 3)"), "3");
+    assert_eq!(check("(when false 1 2 3)"), "()");
+    assert_eq!(check("(unless false {+ 1 2})"), "3");
+    check("(when)");
+    check("(unless 5 1)");
+    check("(when true (+ 1 {}) (print \"not reached\"))");
+    assert_eq!(check("(cond {false 1} {true 2})"), "2");
+    check("(cond {1 1})");
+    check("(cond {})");
+}
+
+#[test]
+fn sequences_and_scopes() {
+    assert_eq!(check("(do)"), "()");
+    assert_eq!(check("(do 1 {+ 1 1} (+ 1 2))"), "3");
+    check("(do 1 (/ 1 0) 3)");
+    assert_eq!(check("(let {a 1 b (+ a 1)} {+ a b})"), "3");
+    assert_eq!(check("(let {} 1)"), "1");
+    assert_eq!(check("(def {a} 10) (let {a 1} {a}) a"), "10");
+    check("(let {a} a)");
+    check("(let {1 2} 3)");
+    check("(let x 1)");
+    check("(let)");
+    check("(let {a (/ 1 0)} a)");
+    check("(let {a 1} (/ a 0) a)");
+    assert_eq!(check("(let {a 1} (= {b} 2) {+ a b})"), "3");
+}
+
+#[test]
+fn rebound_forms() {
+    assert_eq!(check("(def {my-if} if) (my-if true {1} {2})"), "1");
+    assert_eq!(check("(def {if} (\\ {a b c} {b})) (if 1 2 3)"), "2");
+    assert_eq!(check("(def {do} list) (do 1 2)"), "{1 2}");
+    assert_eq!(check("(fun {f if} {if 1 2 3}) (f +)"), "6");
+}
+
+#[test]
+fn host_forms_named_like_builtins() {
+    for evaluator in EVALUATORS {
+        let mut interpreter = interpreter(evaluator);
+        let form = lval_special("if", |_: &Env, _| Step::Value(lval_num(42)));
+        interpreter.env().put("if", form);
+        assert_eq!(
+            eval(&mut interpreter, "(if true 1 2)"),
+            "42",
+            "{:?}",
+            evaluator
+        );
+    }
+}
+
+#[test]
+fn eval_and_quoting() {
+    assert_eq!(check("(eval {+ 1 2})"), "3");
+    assert_eq!(check("(eval (list + 1 2))"), "3");
+    check("(eval 1)");
+    check("(eval {/ 1 0})");
+    assert_eq!(check("(def {x} 5) `(a ,x ,@{1 2})"), "{a 5 1 2}");
+    check("(unquote x)");
+}
+
+#[test]
+fn macros() {
+    let unless = "(defmacro {my-unless c b} {`(if (not ,c) ,b {})})";
+    assert_eq!(
+        check(&format!("{} (my-unless false {{+ 1 2}})", unless)),
+        "3"
+    );
+    assert_eq!(
+        check(&format!("{} (my-unless true {{+ 1 2}})", unless)),
+        "()"
+    );
+    check(&format!("{} (my-unless true)", unless));
+    let swap = "(defmacro {swap a b} {`(do (= {t} ,a) (= {,a} ,b) (= {,b} t))})";
+    check(&format!("{} (def {{x y}} 1 2) (swap x y) (list x y)", swap));
+    assert_eq!(
+        check("(defmacro {twice x} {`(do ,x ,x)}) (def {n} 0) (twice (def {n} (+ n 1))) n"),
+        "2"
+    );
+    check("(defmacro {m x} {x}) (map m {1})");
+}
+
+#[test]
+fn gensyms_are_uninterned() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_str("def {g} (gensym \"t\")").unwrap();
+    let name = interpreter.eval_str("g").unwrap().to_string();
+    let same = format!("== g (str->sym \"{}\")", name);
+    assert_eq!(interpreter.eval_str(&same).unwrap().to_string(), "false");
+    assert_eq!(interpreter.eval_str("== g g").unwrap().to_string(), "true");
+}
+
+#[test]
+fn builtins_calling_back() {
+    assert_eq!(check("(map (\\ {x} {* x x}) {1 2 3})"), "{1 4 9}");
+    assert_eq!(check("(filter (\\ {x} {> x 1}) {1 2 3})"), "{2 3}");
+    assert_eq!(check("(foldl + 0 (range 100))"), "4950");
+    assert_eq!(check("(foldl - 10 {1 2 3})"), "4");
+    assert_eq!(check("(foldr - 10 {1 2 3})"), "-8");
+    assert!(check("(range 0 1000000000000)").contains("of more than 16777216 elements"));
+    assert_eq!(check("(len (range -5 5))"), "10");
+    assert_eq!(
+        check("(foldr (\\ {x acc} {cons x acc}) {} {1 2 3})"),
+        "{1 2 3}"
+    );
+    check("(map (\\ {x} {/ 1 x}) {1 0})");
+    check("(filter (\\ {x} {x}) {1})");
+}
+
+#[test]
+fn collections_and_strings() {
+    assert_eq!(check("(get (assoc [\"a\" 1] \"b\" 2) \"b\")"), "2");
+    assert_eq!(check("(len #{1 2 2 3})"), "3");
+    check("(get [] 1)");
+    /* Map and set literals read as the calls building them, but empty ones
+    as the empty map and set, since (hash-map) is the builtin */
+    assert_eq!(
+        check("{[] [1 2] #{} #{1}}"),
+        "{[] (hash-map 1 2) #{} (hash-set 1)}"
+    );
+    assert_eq!(check("(list [] [1 2] #{} #{1})"), "{[] [1 2] #{} #{1}}");
+    assert_eq!(check("(len {[]})"), "1");
+    assert_eq!(check("(concat \"a\" \"b\")"), "\"ab\"");
+    check("(substr \"abc\" 2 1)");
+}
+
+#[test]
+fn shared_lists_are_not_changed() {
+    let xs = "(def {xs} {3 1 2})";
+    for f in [
+        "cons 0", "reverse", "sort", "init", "tail", "head", "drop 1", "take 1",
+    ] {
+        assert_eq!(check(&format!("{} ({} xs) xs", xs, f)), "{3 1 2}");
+    }
+    assert_eq!(check(&format!("{} (join xs xs) xs", xs)), "{3 1 2}");
+    assert_eq!(
+        check(&format!("{} (def {{ys}} xs) (= {{xs}} 1) ys", xs)),
+        "{3 1 2}"
+    );
+}
+
+#[test]
+fn errors_are_located_alike() {
+    check("(fun {f x} {+ x y})\n(f 1)");
+    check("(def {x} 1)\n(if (> x 0)\n    {head {}}\n    {2})");
+    check("(let {a 1}\n  (print a)\n  (nosuch a))");
+    check("(do (+ 1 1)\n    (tail 1))");
+    assert_eq!(
+        check("(def {x} \"héllo\")\n\t(+ 1 x)").strip_prefix("error: "),
+        Some(
+            "error: Function '+' passed incorrect type for argument 2. Got String, Expected Number.\n \
+             --> test.lspy:2:7\n  |\n2 | \t(+ 1 x)\n  | \t     ^"
+        )
+    );
+}
+
+#[test]
+fn unknown_functions() {
+    assert!(check("(nosuch 1 2)").contains("Unknown function 'nosuch'"));
+    assert!(check("(+ 1 (nosuch 2))").contains("Unknown function 'nosuch'"));
+    assert!(check("(fun {f x} {g x})\n(f 1)").contains("Unknown function 'g'"));
+    /* Called with nothing, the symbol is just evaluated */
+    assert!(check("(nosuch)").contains("Unbound Symbol 'nosuch'"));
+    assert!(check("(+ 1 nosuch)").contains("Unbound Symbol 'nosuch'"));
+}
+
+#[test]
+fn builtin_arguments_are_checked() {
+    assert!(check("(head {1} {2})")
+        .contains("Function 'head' passed incorrect number of arguments. Got 2, Expected 1."));
+    assert!(check("(substr \"abc\")").contains(
+        "Function 'substr' passed incorrect number of arguments. Got 1, Expected 2 or 3."
+    ));
+    assert!(check("(assoc [] 1)").contains(
+        "Function 'assoc' passed incorrect number of arguments. Got 2, Expected 3 or more."
+    ));
+    assert!(check("(map {1} {2})").contains(
+        "Function 'map' passed incorrect type for argument 1. Got Q-Expression, Expected Function."
+    ));
+    assert!(check("(> 1 \"2\")").contains(
+        "Function '>' passed incorrect type for argument 2. Got String, Expected Number."
+    ));
+    assert_eq!(check("(- 5)"), "-5");
+    assert_eq!(check("(quot 7 2)"), "3");
+    assert!(check("(cons 1)").contains("Got 1, Expected 2."));
+    assert!(check("(eval {1} {2})").contains("Got 2, Expected 1."));
+    assert!(check("(join {1} 2)").contains(
+        "Function 'join' passed incorrect type for argument 2. Got Number, Expected Q-Expression."
+    ));
+    assert!(check("(len 1)").contains("Got Number, Expected String, Q-Expression, Map or Set."));
+    assert!(check("(if true)").contains("Got 1, Expected 2 or 3."));
+}
+
+#[test]
+fn help_gives_signatures() {
+    assert_eq!(check("(help head)"), "\"(head list:Q-Expression)\"");
+    assert_eq!(check("(help +)"), "\"(+ x:Number & xs:Number)\"");
+    assert_eq!(
+        check("(help substr)"),
+        "\"(substr s:String start:Number [end:Number])\""
+    );
+    assert_eq!(check("(help if)"), "\"(if test then [else])\"");
+    assert_eq!(
+        check("(help len)"),
+        "\"(len x:String|Q-Expression|Map|Set)\""
+    );
+    assert_eq!(check("(fun {f a & b} {a}) (help f)"), "\"(\\\\ {a & b})\"");
+    check("(help 1)");
+    assert_eq!(
+        check("(def {h} head) (help h)"),
+        "\"(head list:Q-Expression)\""
+    );
+}
+
+#[test]
+fn help_knows_replaced_builtins() {
+    let mut interpreter = Interpreter::new();
+    interpreter.register("head", |args| args);
+    let help = interpreter.eval_str("help head").unwrap();
+    assert_eq!(help.to_string(), "\"(head & args)\"");
+}
+
+#[test]
+fn depth_is_limited() {
+    let deep = "(fun {deep n} {if (== n 0) {0} {+ 1 (deep (- n 1))}}) (deep 1000000)";
+    for evaluator in [Evaluator::TreeWalker, Evaluator::Bytecode] {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_evaluator(evaluator);
+        interpreter.set_max_depth(100);
+        let err = interpreter.eval_source("deep.lspy", deep).unwrap_err();
+        assert!(err.to_string().contains("Recursion depth exceeded"));
+    }
+}
+
+/* The error evaluating deep, nesting 20000 deep, with depth levels allowed
+on a thread with stack bytes of stack */
+fn deep_on_thread(evaluator: Evaluator, depth: usize, stack: usize) -> String {
+    let deep = "(fun {deep n} {if (== n 0) {0} {+ 1 (deep (- n 1))}}) (deep 20000)";
+    thread::Builder::new()
+        .stack_size(stack)
+        .spawn(move || {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_evaluator(evaluator);
+            interpreter.set_max_depth(depth);
+            interpreter
+                .eval_source("deep.lspy", deep)
+                .unwrap_err()
+                .to_string()
+        })
+        .unwrap()
+        .join()
+        .unwrap()
+}
+
+#[test]
+fn default_depth_fits_the_documented_stack() {
+    /* The stack main threads commonly have */
+    const { assert!(DEFAULT_MAX_DEPTH * STACK_PER_DEPTH <= 8 << 20) };
+    for evaluator in EVALUATORS {
+        let err = deep_on_thread(evaluator, DEFAULT_MAX_DEPTH, 8 << 20);
+        assert!(err.contains("Recursion depth exceeded"), "{}", err);
+
+        /* As the rusp binary sizes its thread */
+        let err = deep_on_thread(evaluator, 10_000, 10_000 * STACK_PER_DEPTH);
+        assert!(err.contains("Recursion depth exceeded"), "{}", err);
+    }
+}
+
+#[test]
+fn reading_nesting_is_limited() {
+    /* On a thread with the stack of a main thread */
+    let nested = |n: usize| format!("{}{}", "(".repeat(n), ")".repeat(n));
+    let errors = thread::Builder::new()
+        .stack_size(8 << 20)
+        .spawn(move || {
+            assert!(lval_read("nested.lspy", &nested(1000)).is_ok());
+            [nested(1001), "`{".repeat(200_000)].map(|text| {
+                let err = lval_read("nested.lspy", &text).unwrap_err();
+                (err.message, err.span.column)
+            })
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+    let message = "expressions nested more than 1000 deep".to_string();
+    assert_eq!(errors, [(message.clone(), 1001), (message, 1001)]);
+}
+
+#[test]
+fn self_recursive_closures_survive_collection() {
+    for evaluator in EVALUATORS {
+        let mut interpreter = interpreter(evaluator);
+        let script = "(def {f} (let {} (= {g} (\\ {n} {if (== n 0) {0} {+ 1 (g (- n 1))}})) g))";
+        eval(&mut interpreter, script);
+        assert_eq!(interpreter.collect_garbage(), 0, "{:?}", evaluator);
+        assert_eq!(eval(&mut interpreter, "(f 10)"), "10", "{:?}", evaluator);
+
+        /* Once f is gone, its scope is only held by g */
+        eval(&mut interpreter, "(def {f} ())");
+        assert_eq!(interpreter.collect_garbage(), 1, "{:?}", evaluator);
+    }
+}
+
+#[test]
+fn closures_in_maps_survive_collection() {
+    for evaluator in EVALUATORS {
+        let mut interpreter = interpreter(evaluator);
+        let script = "(fun {make n} {let {m n} (= {fs} [0 (\\ {_} {m})]) fs}) (def {fs} (make 7))";
+        eval(&mut interpreter, script);
+        assert_eq!(interpreter.collect_garbage(), 0, "{:?}", evaluator);
+        assert_eq!(
+            eval(&mut interpreter, "((get fs 0) 0)"),
+            "7",
+            "{:?}",
+            evaluator
+        );
+
+        /* The call's scope and the let's, held by the map they bind */
+        eval(&mut interpreter, "(def {fs} ())");
+        assert_eq!(interpreter.collect_garbage(), 2, "{:?}", evaluator);
+    }
+}
+
+#[test]
+fn collecting_mid_evaluation_keeps_live_scopes() {
+    /* Each call of counter leaves two scopes in a cycle, enough for the
+    heap to collect many times over while loop runs. loop is bound in a
+    scope that it closes over, held only by the evaluation */
+    let script = "(fun {counter n} {let {m n} (= {next} (\\ {} {+ m 1}))}) \
+                  (let {} \
+                    (= {loop} (\\ {n} {if (== n 0) {n} {do (counter n) (loop (- n 1))}})) \
+                    (loop 5000))";
+    for evaluator in EVALUATORS {
+        let mut interpreter = interpreter(evaluator);
+        assert_eq!(eval(&mut interpreter, script), "0", "{:?}", evaluator);
+        assert!(
+            interpreter.collect_garbage() < 5000,
+            "{:?} left every cycle to the end",
+            evaluator
+        );
+    }
+}
+
+#[test]
+fn lists_held_outside_the_heap_survive_collection() {
+    /* burn creates enough scopes to collect while (make 7) is an argument
+    waiting on the native stack */
+    let make = "(fun {make n} {let {m n} (= {fs} (list (\\ {_} {m}))) fs})";
+    let script = format!(
+        "{} (fun {{burn n}} {{if (== n 0) {{0}} {{burn (- n 1)}}}}) \
+         ((\\ {{fs x}} {{(nth 0 fs) 0}}) (make 7) (burn 5000))",
+        make
+    );
+    assert_eq!(check(&script), "7");
+
+    /* A host holding the list */
+    for evaluator in EVALUATORS {
+        let mut interpreter = interpreter(evaluator);
+        eval(&mut interpreter, make);
+        let fs = interpreter.eval_str("make 7").unwrap();
+        assert_eq!(interpreter.collect_garbage(), 0, "{:?}", evaluator);
+        interpreter.env().def("fs", fs);
+        assert_eq!(
+            eval(&mut interpreter, "((nth 0 fs) 0)"),
+            "7",
+            "{:?}",
+            evaluator
+        );
+    }
+}